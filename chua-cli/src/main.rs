@@ -1,5 +1,6 @@
-use chua::{upload, ChuaResult};
+use chua::{upload_with_info, ChuaResult, ClientOptions, FileInfo};
 use std::path::PathBuf;
+use std::time::Duration;
 use structopt::StructOpt;
 use url::Url;
 
@@ -15,13 +16,52 @@ struct Opts {
     #[structopt(short, long)]
     parallel: usize,
 
-    /// chunk Size
-    #[structopt(short, long)]
+    /// chunk Size, 0 to let the server decide
+    #[structopt(short, long, default_value = "0")]
     chunk_size: u64,
 
     /// file to upload
     #[structopt(short, long, parse(from_os_str))]
     file: PathBuf,
+
+    /// request timeout in seconds
+    #[structopt(long, default_value = "20")]
+    timeout: u64,
+
+    /// http proxy, e.g. http://proxy.example.com:8080
+    #[structopt(long)]
+    proxy: Option<String>,
+
+    /// limit the total upload rate in bytes per second
+    #[structopt(long)]
+    limit_rate: Option<u64>,
+
+    /// extra trusted root certificate (PEM), can be given multiple times
+    #[structopt(long, parse(from_os_str))]
+    ca_cert: Vec<PathBuf>,
+
+    /// client certificate chain and private key (PEM) for mutual TLS
+    #[structopt(long, parse(from_os_str))]
+    identity: Option<PathBuf>,
+
+    /// file name to report to the server, defaults to the local file name
+    #[structopt(long)]
+    name: Option<String>,
+
+    /// MIME type, detected from the extension if not given
+    #[structopt(long)]
+    content_type: Option<String>,
+
+    /// custom metadata in KEY=VALUE form, can be given multiple times
+    #[structopt(long, parse(try_from_str = parse_key_value))]
+    meta: Vec<(String, String)>,
+}
+
+fn parse_key_value(s: &str) -> Result<(String, String), String> {
+    match s.find('=') {
+        Some(index) => Ok((s[..index].to_string(), s[index + 1..].to_string())),
+        None => Err(format!("invalid KEY=VALUE: no `=` found in `{}`", s)),
+    }
 }
 
 #[tokio::main]
@@ -31,9 +71,42 @@ async fn main() -> ChuaResult<()> {
         file,
         chunk_size,
         parallel,
+        timeout,
+        proxy,
+        limit_rate,
+        ca_cert,
+        identity,
+        name,
+        content_type,
+        meta,
     } = Opts::from_args();
 
-    let file_id = upload(base_url, &file, chunk_size, parallel).await?;
+    let mut root_certificates = Vec::with_capacity(ca_cert.len());
+    for path in ca_cert {
+        root_certificates.push(tokio::fs::read(path).await?);
+    }
+
+    let identity = match identity {
+        Some(path) => Some(tokio::fs::read(path).await?),
+        None => None,
+    };
+
+    let options = ClientOptions {
+        timeout: Duration::from_secs(timeout),
+        proxy,
+        root_certificates,
+        identity,
+        max_bytes_per_second: limit_rate,
+    };
+
+    let info = FileInfo {
+        name,
+        content_type,
+        metadata: meta.into_iter().collect(),
+        md5: None,
+    };
+
+    let file_id = upload_with_info(base_url, &file, chunk_size, parallel, info, &options).await?;
 
     println!("File {} uploaded.(id: {})", file.display(), file_id);
 
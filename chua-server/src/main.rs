@@ -1,26 +1,54 @@
 mod reply;
 
-use crate::reply::{CompleteReply, InitializeReply, UploadChunkReply};
+use crate::reply::{
+    AbortReply, AppendReply, CompleteReply, InitializeReply, LimitsReply, StatusReply,
+    UploadChunkReply,
+};
 use bytes::Buf;
 use chua::{
-    CompleteError, CompleteResult, InitializeError, InitializeParam, InitializeResult,
-    UploadChunkError, UploadChunkResult, PART_NAME,
+    negotiate_capabilities, AbortError, AbortResult, AppendError, AppendParam, AppendResult,
+    CompleteError, CompleteResult, FileStatus, InitializeError, InitializeParam, InitializeResult,
+    Limits, StatusError, StatusResult, UploadChunkError, UploadChunkResult, UploadState,
+    CAPABILITIES, PART_NAME, PROTOCOL_VERSION,
 };
+use serde::{Deserialize, Serialize};
 use std::convert::Infallible;
+use std::ops::Range;
 use std::path::{Path, PathBuf};
 use structopt::StructOpt;
-use tokio::fs::{create_dir_all, remove_dir_all, File, OpenOptions};
+use tokio::fs::{create_dir_all, remove_dir_all, remove_file, File, OpenOptions};
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::stream::StreamExt;
 use uuid::Uuid;
+use warp::http::header::{CONTENT_DISPOSITION, CONTENT_TYPE};
+use warp::http::HeaderValue;
 use warp::multipart::FormData;
-use warp::Filter;
+use warp::reply::Response;
+use warp::{Filter, Reply};
 
 #[macro_use]
 extern crate log;
 
 const META_FILE_NAME: &'static str = ".meta";
 
+/// 进行中的追加上传的信息，与原文件的 `.meta` 分开保存，完成后合并进 `.meta`
+const APPEND_FILE_NAME: &'static str = ".append";
+
+/// 保存在分片目录中的上传信息
+#[derive(Serialize, Deserialize, Debug)]
+struct Meta {
+    #[serde(flatten)]
+    param: InitializeParam,
+
+    /// 追加上传时已有文件的大小，分片合并后接在它后面
+    #[serde(default)]
+    offset: u64,
+
+    /// 上传的状态
+    #[serde(default)]
+    state: UploadState,
+}
+
 #[derive(Debug, Clone, StructOpt)]
 #[structopt(name = "chua-server")]
 struct Opts {
@@ -52,6 +80,12 @@ async fn main() {
 
     let opts = Opts::from_args();
 
+    warp::serve(routes(opts.clone()))
+        .run(([0, 0, 0, 0], opts.port))
+        .await;
+}
+
+fn routes(opts: Opts) -> impl Filter<Extract = impl Reply, Error = warp::Rejection> + Clone {
     let with_opts = {
         let opts = opts.clone();
         warp::any().map(move || opts.clone())
@@ -72,8 +106,8 @@ async fn main() {
 
                     let chunk_dir = opts.temp_dir.join(file_id.to_string());
 
-                    let meta = match read_meta(&chunk_dir).await {
-                        Ok(meta) => meta,
+                    let meta = match read_current_meta(&chunk_dir).await {
+                        Ok((meta, _)) => meta,
                         Err(e) => {
                             return Ok(UploadChunkResult::Err {
                                 error: UploadChunkError::Other {
@@ -84,6 +118,31 @@ async fn main() {
                         }
                     };
 
+                    if meta.state != UploadState::Uploading {
+                        return Ok(UploadChunkResult::Err {
+                            error: UploadChunkError::Other {
+                                detail: "The upload is no longer accepting chunks".into(),
+                            },
+                        }
+                        .into());
+                    }
+
+                    // 超出范围的序号不能落盘，否则空的请求体也会被当作大小正确的分片
+                    let count = chunk_count(&meta.param);
+                    if index >= count {
+                        return Ok(UploadChunkResult::Err {
+                            error: UploadChunkError::Other {
+                                detail: format!(
+                                    "Chunk {} is out of range, the upload has {} chunks",
+                                    index, count
+                                ),
+                            },
+                        }
+                        .into());
+                    }
+
+                    let expected = expected_chunk_size(&meta.param, index);
+
                     while let Some(result) = form.next().await {
                         match result {
                             Ok(mut part) if part.name() == PART_NAME => {
@@ -93,14 +152,14 @@ async fn main() {
                                             let chunk_path = chunk_dir.join(index.to_string());
                                             match save_chunk(&chunk_path, data).await {
                                                 Ok(size) => {
-                                                    if size == meta.chunk_size {
+                                                    if size == expected {
                                                         Ok::<UploadChunkReply, Infallible>(
                                                             UploadChunkResult::Ok.into(),
                                                         )
                                                     } else {
                                                         Ok(UploadChunkResult::Err {
                                                             error: UploadChunkError::Size {
-                                                                expected: meta.chunk_size,
+                                                                expected,
                                                                 actual: size,
                                                             },
                                                         }
@@ -125,7 +184,7 @@ async fn main() {
                                 } else {
                                     Ok(UploadChunkResult::Err {
                                         error: UploadChunkError::Size {
-                                            expected: meta.chunk_size,
+                                            expected,
                                             actual: 0,
                                         },
                                     }
@@ -147,7 +206,7 @@ async fn main() {
                     Ok::<UploadChunkReply, Infallible>(
                         UploadChunkResult::Err {
                             error: UploadChunkError::Size {
-                                expected: meta.chunk_size,
+                                expected,
                                 actual: 0,
                             },
                         }
@@ -163,6 +222,7 @@ async fn main() {
         warp::post()
             .and(with_opts.clone())
             .and(warp::path("file"))
+            .and(warp::path::end())
             .and(warp::body::json())
             .and_then(move |opts: Opts, param: InitializeParam| {
                 async move {
@@ -189,14 +249,25 @@ async fn main() {
                     let id = Uuid::new_v4();
                     let chunk_dir = opts.temp_dir.join(id.to_string());
 
-                    if let Err(error) = initialize(param, &chunk_dir).await {
-                        return Ok(InitializeResult::Err { error }.into());
+                    let version = param.version.min(PROTOCOL_VERSION);
+                    let capabilities = negotiate_capabilities(CAPABILITIES, &param.capabilities);
+
+                    let meta = Meta {
+                        param,
+                        offset: 0,
+                        state: UploadState::Uploading,
+                    };
+
+                    if let Err(e) = write_meta(&meta, &chunk_dir, META_FILE_NAME).await {
+                        return Ok(InitializeResult::Err { error: e.into() }.into());
                     }
 
                     Ok::<InitializeReply, Infallible>(
                         InitializeResult::Ok {
                             id,
                             duplicated: false,
+                            version,
+                            capabilities,
                         }
                         .into(),
                     )
@@ -210,6 +281,7 @@ async fn main() {
         .and(with_opts.clone())
         .and(warp::path("file"))
         .and(warp::path::param())
+        .and(warp::path::end())
         .and_then(|opts: Opts, file_id: Uuid| async move {
             debug!("upload_complete: {}", file_id);
             // 检查所有的分片是否都在
@@ -217,7 +289,7 @@ async fn main() {
 
             match build_file(file_id, opts.static_dir, &chunk_dir).await {
                 Ok(meta) => {
-                    info!("File {}.{} completed.", file_id, meta.extension);
+                    info!("File {}.{} completed.", file_id, meta.param.extension);
                 }
                 Err(error) => return Ok(CompleteResult::Err { error }.into()),
             }
@@ -225,37 +297,224 @@ async fn main() {
             Ok::<CompleteReply, Infallible>(CompleteResult::Ok.into())
         });
 
-    let file = warp::get().and(warp::fs::dir(opts.static_dir));
+    // 追加上传
+    // POST /file/{fileId}/append
+    let append = warp::post()
+        .and(with_opts.clone())
+        .and(warp::path("file"))
+        .and(warp::path::param())
+        .and(warp::path("append"))
+        .and(warp::path::end())
+        .and(warp::body::json())
+        .and_then(|opts: Opts, file_id: Uuid, param: AppendParam| async move {
+            debug!("append: {}", file_id);
+
+            if let Err(error) = append(file_id, param, &opts).await {
+                return Ok(AppendResult::Err { error }.into());
+            }
+
+            Ok::<AppendReply, Infallible>(AppendResult::Ok.into())
+        });
 
-    let routes = initialize.or(upload_chunk).or(complete).or(file);
+    // 查询服务端的限制
+    // GET /limits
+    let limits = warp::get()
+        .and(with_opts.clone())
+        .and(warp::path("limits"))
+        .and(warp::path::end())
+        .map(|opts: Opts| -> LimitsReply {
+            Limits {
+                max_file_size: opts.max_file_size,
+                max_chunk_size: opts.max_chunk_size,
+                version: PROTOCOL_VERSION,
+                capabilities: CAPABILITIES.to_vec(),
+            }
+            .into()
+        });
 
-    warp::serve(routes).run(([0, 0, 0, 0], opts.port)).await;
+    // 放弃上传，删除分片目录
+    // DELETE /file/{fileId}
+    let abort = warp::delete()
+        .and(with_opts.clone())
+        .and(warp::path("file"))
+        .and(warp::path::param())
+        .and(warp::path::end())
+        .and_then(|opts: Opts, file_id: Uuid| async move {
+            debug!("abort: {}", file_id);
+
+            if let Err(error) = abort(file_id, &opts).await {
+                return Ok(AbortResult::Err { error }.into());
+            }
+
+            info!("Upload {} aborted.", file_id);
+
+            Ok::<AbortReply, Infallible>(AbortResult::Ok.into())
+        });
+
+    // 查询文件信息
+    // GET /file/{fileId}
+    let status = warp::get()
+        .and(with_opts.clone())
+        .and(warp::path("file"))
+        .and(warp::path::param())
+        .and(warp::path::end())
+        .and_then(|opts: Opts, file_id: Uuid| async move {
+            debug!("status: {}", file_id);
+
+            let status = match status(file_id, &opts).await {
+                Ok(status) => status,
+                Err(error) => return Ok(StatusResult::Err { error }.into()),
+            };
+
+            Ok::<StatusReply, Infallible>(StatusResult::Ok(status).into())
+        });
+
+    // 下载已完成的文件，按上传时提交的信息设置 Content-Type 和 Content-Disposition。
+    // 客户端分片下载前用 HEAD 取文件大小
+    // GET|HEAD /{fileId}.{ext}
+    let file = warp::get()
+        .or(warp::head())
+        .unify()
+        .and(with_opts.clone())
+        .and(warp::fs::dir(opts.static_dir.clone()))
+        .and_then(|opts: Opts, file: warp::fs::File| async move {
+            let meta = match file.path().file_stem().and_then(|s| s.to_str()) {
+                Some(stem) => match stem.parse::<Uuid>() {
+                    Ok(file_id) => {
+                        read_meta(opts.temp_dir.join(file_id.to_string()), META_FILE_NAME)
+                            .await
+                            .ok()
+                    }
+                    Err(_) => None,
+                },
+                None => None,
+            };
+
+            let mut res = file.into_response();
+
+            if let Some(Meta { param, .. }) = meta {
+                let headers = res.headers_mut();
+
+                if let Ok(value) = HeaderValue::from_str(&param.content_type) {
+                    if !param.content_type.is_empty() {
+                        headers.insert(CONTENT_TYPE, value);
+                    }
+                }
+
+                if !param.name.is_empty() {
+                    let disposition =
+                        format!("inline; filename*=UTF-8''{}", percent_encode(&param.name));
+
+                    if let Ok(value) = HeaderValue::from_str(&disposition) {
+                        headers.insert(CONTENT_DISPOSITION, value);
+                    }
+                }
+            }
+
+            Ok::<Response, Infallible>(res)
+        });
+
+    initialize
+        .or(upload_chunk)
+        .or(append)
+        .or(complete)
+        .or(abort)
+        .or(status)
+        .or(limits)
+        .or(file)
 }
 
-async fn initialize(
-    param: InitializeParam,
+/// 把上传信息写到分片目录中的 `name` 文件
+async fn write_meta(
+    meta: &Meta,
     chunk_dir: impl AsRef<Path>,
-) -> Result<(), InitializeError> {
+    name: &str,
+) -> Result<(), std::io::Error> {
     let chunk_dir = chunk_dir.as_ref();
     create_dir_all(&chunk_dir).await?;
 
-    let meta_file_path = chunk_dir.join(META_FILE_NAME);
+    let meta_file_path = chunk_dir.join(name);
 
     let mut meta_file = OpenOptions::new()
-        .create_new(true)
+        .create(true)
         .write(true)
+        .truncate(true)
         .open(meta_file_path)
         .await?;
 
-    let meta = serde_json::to_string(&param)?;
+    let meta = serde_json::to_string(meta)?;
 
     meta_file.write_all(meta.as_bytes()).await?;
 
     Ok(())
 }
 
-async fn read_meta(chunk_dir: impl AsRef<Path>) -> Result<InitializeParam, std::io::Error> {
-    let meta_file_path = chunk_dir.as_ref().join(META_FILE_NAME);
+async fn append(file_id: Uuid, param: AppendParam, opts: &Opts) -> Result<(), AppendError> {
+    if param.size == 0 || param.offset + param.size > opts.max_file_size {
+        return Err(AppendError::Size {
+            max: opts.max_file_size,
+        });
+    }
+
+    if param.chunk_size == 0 || param.chunk_size > opts.max_chunk_size {
+        return Err(AppendError::ChunkSize {
+            max: opts.max_chunk_size,
+        });
+    }
+
+    let path = target_path(&opts.static_dir, file_id, &param.extension);
+
+    let size = match path.metadata() {
+        Ok(meta) if meta.is_file() => meta.len(),
+        _ => return Err(AppendError::NotFound),
+    };
+
+    if size != param.offset
+        || !file_md5(&path)
+            .await?
+            .eq_ignore_ascii_case(&param.prefix_md5)
+    {
+        return Err(AppendError::Prefix { size });
+    }
+
+    let chunk_dir = opts.temp_dir.join(file_id.to_string());
+
+    if chunk_dir.join(APPEND_FILE_NAME).is_file() {
+        return Err(AppendError::Other {
+            detail: "Another append is in progress".into(),
+        });
+    }
+
+    // 沿用上传时提交的文件名、MIME 类型和元数据
+    let (name, content_type, metadata) = match read_meta(&chunk_dir, META_FILE_NAME).await {
+        Ok(Meta { param, .. }) => (param.name, param.content_type, param.metadata),
+        Err(_) => Default::default(),
+    };
+
+    let meta = Meta {
+        param: InitializeParam {
+            size: param.size,
+            chunk_size: param.chunk_size,
+            extension: param.extension,
+            md5: param.md5,
+            name,
+            content_type,
+            metadata,
+            version: PROTOCOL_VERSION,
+            capabilities: CAPABILITIES.to_vec(),
+        },
+        offset: param.offset,
+        state: UploadState::Uploading,
+    };
+
+    write_meta(&meta, chunk_dir, APPEND_FILE_NAME).await?;
+
+    Ok(())
+}
+
+/// 读取分片目录中 `name` 文件保存的上传信息
+async fn read_meta(chunk_dir: impl AsRef<Path>, name: &str) -> Result<Meta, std::io::Error> {
+    let meta_file_path = chunk_dir.as_ref().join(name);
 
     let mut meta_file = File::open(meta_file_path).await?;
 
@@ -265,81 +524,280 @@ async fn read_meta(chunk_dir: impl AsRef<Path>) -> Result<InitializeParam, std::
     Ok(serde_json::from_str(&meta)?)
 }
 
+/// 读取正在接收分片的上传：有未完成的追加时是追加的信息，否则是原文件的信息。同时返回信息所在的文件名
+async fn read_current_meta(
+    chunk_dir: impl AsRef<Path>,
+) -> Result<(Meta, &'static str), std::io::Error> {
+    let chunk_dir = chunk_dir.as_ref();
+
+    match read_meta(chunk_dir, APPEND_FILE_NAME).await {
+        Ok(meta) => Ok((meta, APPEND_FILE_NAME)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            Ok((read_meta(chunk_dir, META_FILE_NAME).await?, META_FILE_NAME))
+        }
+        Err(e) => Err(e),
+    }
+}
+
 async fn build_file(
     file_id: Uuid,
     target_dir: impl AsRef<Path>,
     chunk_dir: impl AsRef<Path>,
-) -> Result<InitializeParam, CompleteError> {
-    let meta = read_meta(chunk_dir.as_ref()).await?;
+) -> Result<Meta, CompleteError> {
+    let chunk_dir = chunk_dir.as_ref();
+    let (mut meta, name) = read_current_meta(chunk_dir).await?;
+
+    match meta.state {
+        UploadState::Uploading => {}
+        UploadState::Completing => {
+            return Err(CompleteError::Other {
+                detail: "The upload is being completed".into(),
+            })
+        }
+        UploadState::Completed => return Ok(meta),
+    }
+
+    let missing = ranges(&received_chunks(chunk_dir, &meta.param), false);
+
+    if !missing.is_empty() {
+        return Err(CompleteError::Incomplete { missing });
+    }
+
+    meta.state = UploadState::Completing;
+    write_meta(&meta, chunk_dir, name).await?;
+
+    if let Err(e) = merge_chunks(file_id, target_dir, chunk_dir, &meta).await {
+        meta.state = UploadState::Uploading;
+        write_meta(&meta, chunk_dir, name).await?;
+        return Err(e);
+    }
+
+    // 只删除分片，保留 .meta 供查询和下载时使用
+    remove_chunks(chunk_dir, &meta.param).await;
+
+    if name == APPEND_FILE_NAME {
+        // 追加完成，原文件的信息改为合并后的大小，md5 不再对应整个文件
+        let mut file_meta = match read_meta(chunk_dir, META_FILE_NAME).await {
+            Ok(file_meta) => file_meta,
+            Err(_) => Meta {
+                param: meta.param.clone(),
+                offset: 0,
+                state: UploadState::Completed,
+            },
+        };
+
+        file_meta.param.size = meta.offset + meta.param.size;
+        file_meta.param.md5 = String::new();
+        file_meta.state = UploadState::Completed;
+
+        write_meta(&file_meta, chunk_dir, META_FILE_NAME).await?;
+        remove_file(chunk_dir.join(APPEND_FILE_NAME)).await?;
+
+        return Ok(file_meta);
+    }
+
+    meta.state = UploadState::Completed;
+    write_meta(&meta, chunk_dir, name).await?;
+
+    Ok(meta)
+}
+
+/// 删除 `param` 对应的所有分片
+async fn remove_chunks(chunk_dir: &Path, param: &InitializeParam) {
+    for i in 0..chunk_count(param) {
+        let _ = remove_file(chunk_dir.join(i.to_string())).await;
+    }
+}
 
-    let quotient = meta.size / meta.chunk_size;
-    let remainder = meta.size % meta.chunk_size;
+/// 把所有分片按顺序合并到目标文件，失败时去掉写了一半的内容
+async fn merge_chunks(
+    file_id: Uuid,
+    target_dir: impl AsRef<Path>,
+    chunk_dir: &Path,
+    meta: &Meta,
+) -> Result<(), CompleteError> {
+    let target_path = target_path(target_dir, file_id, &meta.param.extension);
+    let mut target = if meta.offset > 0 {
+        let target = OpenOptions::new().append(true).open(&target_path).await?;
+
+        if target.metadata().await?.len() != meta.offset {
+            return Err(CompleteError::Other {
+                detail: "The file has changed since the append started".into(),
+            });
+        }
 
-    let (chunk_count, tail_chunk_size) = if remainder == 0 {
-        (quotient as usize, meta.chunk_size)
+        target
     } else {
-        (quotient as usize + 1, remainder)
+        OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(&target_path)
+            .await?
     };
 
-    let mut ranges = Vec::new();
-    let mut range = 0..chunk_count;
-    for i in 0..chunk_count {
-        let chunk_path = chunk_dir.as_ref().join(i.to_string());
-        if chunk_path.exists() && chunk_path.is_file() {
-            if let Ok(file_meta) = chunk_path.metadata() {
-                let len = file_meta.len();
-
-                // 这一片应该是多少
-                let chunk_size = if i == chunk_count - 1 {
-                    tail_chunk_size
-                } else {
-                    meta.chunk_size
-                };
-
-                if len == chunk_size {
-                    if range.start < i {
-                        let r = range.start..i;
-                        ranges.push(r);
-                        range.start = i + 1;
-                    } else {
-                        range.start += 1;
-                    }
-                }
-            }
+    if let Err(e) = copy_chunks(chunk_dir, &meta.param, &mut target).await {
+        // 追加时恢复成原来的文件，新上传时不留下不完整的文件
+        let _ = if meta.offset > 0 {
+            target.set_len(meta.offset).await
+        } else {
+            drop(target);
+            remove_file(&target_path).await
+        };
+
+        return Err(e.into());
+    }
+
+    Ok(())
+}
+
+async fn copy_chunks(
+    chunk_dir: &Path,
+    param: &InitializeParam,
+    target: &mut File,
+) -> Result<(), std::io::Error> {
+    for i in 0..chunk_count(param) {
+        let mut file = File::open(chunk_dir.join(i.to_string())).await?;
+
+        tokio::io::copy(&mut file, target).await?;
+    }
+
+    target.flush().await
+}
+
+/// 分片总数
+// `u64::div_ceil` 需要较新的编译器
+#[allow(clippy::manual_div_ceil)]
+fn chunk_count(param: &InitializeParam) -> usize {
+    ((param.size + param.chunk_size - 1) / param.chunk_size) as usize
+}
+
+/// 每个分片是否已收到且大小正确
+fn received_chunks(chunk_dir: &Path, param: &InitializeParam) -> Vec<bool> {
+    (0..chunk_count(param))
+        .map(|i| match chunk_dir.join(i.to_string()).metadata() {
+            Ok(meta) => meta.is_file() && meta.len() == expected_chunk_size(param, i),
+            Err(_) => false,
+        })
+        .collect()
+}
+
+/// 把 `flags` 中连续为 `value` 的序号合并成区间
+fn ranges(flags: &[bool], value: bool) -> Vec<Range<usize>> {
+    let mut ranges: Vec<Range<usize>> = Vec::new();
+
+    for (i, flag) in flags.iter().enumerate() {
+        if *flag != value {
+            continue;
+        }
+
+        match ranges.last_mut() {
+            Some(range) if range.end == i => range.end = i + 1,
+            _ => ranges.push(i..i + 1),
         }
     }
 
-    if range.start < chunk_count {
-        ranges.push(range);
+    ranges
+}
+
+async fn abort(file_id: Uuid, opts: &Opts) -> Result<(), AbortError> {
+    let chunk_dir = opts.temp_dir.join(file_id.to_string());
+
+    if !chunk_dir.is_dir() {
+        return Err(AbortError::NotFound);
+    }
+
+    let (meta, name) = read_current_meta(&chunk_dir).await?;
+
+    if meta.state != UploadState::Uploading {
+        return Err(AbortError::Other {
+            detail: "The upload is already completed".into(),
+        });
     }
 
-    if !ranges.is_empty() {
-        return Err(CompleteError::Incomplete { missing: ranges });
+    if name == APPEND_FILE_NAME {
+        // 只放弃追加，保留原文件和它的信息
+        remove_chunks(&chunk_dir, &meta.param).await;
+        remove_file(chunk_dir.join(APPEND_FILE_NAME)).await?;
+    } else {
+        remove_dir_all(chunk_dir).await?;
     }
 
-    let target_path = {
-        let mut p = target_dir.as_ref().join(file_id.to_string());
-        p.set_extension(&meta.extension);
-        p
+    Ok(())
+}
+
+/// 查询上传进度，有未完成的追加时返回追加部分的进度
+async fn status(file_id: Uuid, opts: &Opts) -> Result<FileStatus, StatusError> {
+    let chunk_dir = opts.temp_dir.join(file_id.to_string());
+
+    let Meta { param, state, .. } = match read_current_meta(&chunk_dir).await {
+        Ok((meta, _)) => meta,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Err(StatusError::NotFound),
+        Err(e) => return Err(e.into()),
     };
-    let mut target = OpenOptions::new()
-        .create(true)
-        .write(true)
-        .open(target_path)
-        .await?;
 
-    for i in 0..chunk_count {
-        let mut file = File::open(chunk_dir.as_ref().join(i.to_string())).await?;
+    // 完成后分片已被删除
+    let received = if state == UploadState::Completed {
+        ranges(&vec![true; chunk_count(&param)], true)
+    } else {
+        ranges(&received_chunks(&chunk_dir, &param), true)
+    };
+
+    Ok(FileStatus {
+        received,
+        state,
+        size: param.size,
+        chunk_size: param.chunk_size,
+        extension: param.extension,
+        name: param.name,
+        content_type: param.content_type,
+        metadata: param.metadata,
+    })
+}
+
+/// 按 RFC 5987 编码 `Content-Disposition` 中的文件名
+fn percent_encode(s: &str) -> String {
+    let mut encoded = String::with_capacity(s.len());
+
+    for b in s.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                encoded.push(b as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", b)),
+        }
+    }
 
-        tokio::io::copy(&mut file, &mut target).await?;
+    encoded
+}
+
+fn target_path(target_dir: impl AsRef<Path>, file_id: Uuid, extension: &str) -> PathBuf {
+    let mut p = target_dir.as_ref().join(file_id.to_string());
+    p.set_extension(extension);
+    p
+}
+
+async fn file_md5(path: impl AsRef<Path>) -> Result<String, std::io::Error> {
+    let mut file = File::open(path).await?;
+    let mut context = md5::Context::new();
+    let mut buf = vec![0; 1024 * 1024];
+
+    loop {
+        let n = file.read(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+        context.consume(&buf[..n]);
     }
 
-    target.flush().await?;
-    drop(target);
+    Ok(format!("{:x}", context.compute()))
+}
 
-    tokio::spawn(remove_dir_all(chunk_dir.as_ref().to_owned()));
+/// 第 `index` 个分片应有的大小，最后一片可能不足 `chunk_size`
+fn expected_chunk_size(meta: &InitializeParam, index: usize) -> u64 {
+    let start = index as u64 * meta.chunk_size;
 
-    Ok(meta)
+    meta.size.saturating_sub(start).min(meta.chunk_size)
 }
 
 async fn save_chunk(
@@ -361,3 +819,459 @@ async fn save_chunk(
 
     Ok(size)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chua::{download, ChuaError, ClientOptions};
+    use std::collections::HashMap;
+    use std::net::SocketAddr;
+
+    fn param(size: u64, chunk_size: u64) -> InitializeParam {
+        InitializeParam {
+            size,
+            chunk_size,
+            extension: String::new(),
+            md5: String::new(),
+            name: String::new(),
+            content_type: String::new(),
+            metadata: HashMap::new(),
+            version: 0,
+            capabilities: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn only_the_last_chunk_may_be_short() {
+        let param = param(10, 4);
+
+        assert_eq!(chunk_count(&param), 3);
+        assert_eq!(expected_chunk_size(&param, 0), 4);
+        assert_eq!(expected_chunk_size(&param, 1), 4);
+        assert_eq!(expected_chunk_size(&param, 2), 2);
+        assert_eq!(expected_chunk_size(&param, 3), 0);
+    }
+
+    #[test]
+    fn exact_multiples_have_full_last_chunks() {
+        let param = param(8, 4);
+
+        assert_eq!(chunk_count(&param), 2);
+        assert_eq!(expected_chunk_size(&param, 1), 4);
+        assert_eq!(expected_chunk_size(&param, 2), 0);
+    }
+
+    /// 每个测试用自己的目录，避免并行执行时互相影响
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("chua-server-{}-{}", std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn opts(name: &str) -> Opts {
+        let dir = temp_dir(name);
+        let static_dir = dir.join("static");
+        let temp_dir = dir.join("temp");
+        std::fs::create_dir_all(&static_dir).unwrap();
+        std::fs::create_dir_all(&temp_dir).unwrap();
+
+        Opts {
+            port: 0,
+            max_chunk_size: 1024,
+            max_file_size: 1024 * 1024,
+            static_dir,
+            temp_dir,
+        }
+    }
+
+    fn md5_hex(data: &[u8]) -> String {
+        format!("{:x}", md5::compute(data))
+    }
+
+    async fn initialize(opts: &Opts, data: &[u8], chunk_size: u64) -> Uuid {
+        let param = InitializeParam {
+            extension: "txt".into(),
+            md5: md5_hex(data),
+            name: "测试 1.txt".into(),
+            content_type: "text/plain".into(),
+            ..param(data.len() as u64, chunk_size)
+        };
+
+        let resp = warp::test::request()
+            .method("POST")
+            .path("/file")
+            .json(&param)
+            .reply(&routes(opts.clone()))
+            .await;
+
+        match serde_json::from_slice::<InitializeResult>(resp.body()).unwrap() {
+            InitializeResult::Ok { id, .. } => id,
+            InitializeResult::Err { error } => panic!("initialize failed: {}", error),
+        }
+    }
+
+    async fn upload_chunk(opts: &Opts, id: Uuid, index: usize, chunk: &[u8]) -> UploadChunkResult {
+        const BOUNDARY: &str = "chua-test-boundary";
+
+        let mut body = format!(
+            "--{}\r\nContent-Disposition: form-data; name=\"{}\"; filename=\"{}\"\r\n\
+             Content-Type: application/octet-stream\r\n\r\n",
+            BOUNDARY, PART_NAME, id
+        )
+        .into_bytes();
+        body.extend_from_slice(chunk);
+        body.extend_from_slice(format!("\r\n--{}--\r\n", BOUNDARY).as_bytes());
+
+        let resp = warp::test::request()
+            .method("PUT")
+            .path(&format!("/file/{}/{}", id, index))
+            .header(
+                "content-type",
+                format!("multipart/form-data; boundary={}", BOUNDARY),
+            )
+            .body(body)
+            .reply(&routes(opts.clone()))
+            .await;
+
+        serde_json::from_slice(resp.body()).unwrap()
+    }
+
+    async fn upload_chunks(opts: &Opts, id: Uuid, data: &[u8], chunk_size: usize) {
+        for (index, chunk) in data.chunks(chunk_size).enumerate() {
+            match upload_chunk(opts, id, index, chunk).await {
+                UploadChunkResult::Ok => {}
+                UploadChunkResult::Err { error } => panic!("chunk {} failed: {}", index, error),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn rejects_chunks_past_the_end() {
+        let opts = &opts("past-the-end");
+        let id = initialize(opts, b"abcdef", 4).await;
+
+        // 最后一个分片之后的序号应有的大小是 0，空的分片也不能被接受
+        assert!(matches!(
+            upload_chunk(opts, id, 2, b"").await,
+            UploadChunkResult::Err {
+                error: UploadChunkError::Other { .. }
+            }
+        ));
+        assert!(!opts.temp_dir.join(id.to_string()).join("2").exists());
+
+        upload_chunks(opts, id, b"abcdef", 4).await;
+        complete(opts, id).await;
+    }
+
+    async fn complete(opts: &Opts, id: Uuid) {
+        let resp = warp::test::request()
+            .method("POST")
+            .path(&format!("/file/{}", id))
+            .reply(&routes(opts.clone()))
+            .await;
+
+        match serde_json::from_slice::<CompleteResult>(resp.body()).unwrap() {
+            CompleteResult::Ok => {}
+            CompleteResult::Err { error } => panic!("complete failed: {}", error),
+        }
+    }
+
+    async fn status(opts: &Opts, id: Uuid) -> FileStatus {
+        let resp = warp::test::request()
+            .path(&format!("/file/{}", id))
+            .reply(&routes(opts.clone()))
+            .await;
+
+        match serde_json::from_slice::<StatusResult>(resp.body()).unwrap() {
+            StatusResult::Ok(status) => status,
+            StatusResult::Err { error } => panic!("status failed: {}", error),
+        }
+    }
+
+    async fn append(
+        opts: &Opts,
+        id: Uuid,
+        prefix: &[u8],
+        data: &[u8],
+        chunk_size: u64,
+    ) -> AppendResult {
+        let param = AppendParam {
+            offset: prefix.len() as u64,
+            size: data.len() as u64,
+            chunk_size,
+            extension: "txt".into(),
+            prefix_md5: md5_hex(prefix),
+            md5: md5_hex(data),
+        };
+
+        let resp = warp::test::request()
+            .method("POST")
+            .path(&format!("/file/{}/append", id))
+            .json(&param)
+            .reply(&routes(opts.clone()))
+            .await;
+
+        serde_json::from_slice(resp.body()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn append_updates_status_and_file() {
+        let opts = &opts("append");
+
+        let id = initialize(opts, b"abcdef", 4).await;
+        upload_chunks(opts, id, b"abcdef", 4).await;
+        complete(opts, id).await;
+
+        assert!(matches!(
+            append(opts, id, b"abcdef", b"ghi", 2).await,
+            AppendResult::Ok
+        ));
+
+        // 追加进行中时查询的是追加部分
+        let appending = status(opts, id).await;
+        assert_eq!(appending.state, UploadState::Uploading);
+        assert_eq!(appending.size, 3);
+        assert!(appending.received.is_empty());
+
+        // 同一时间只能有一个追加
+        assert!(matches!(
+            append(opts, id, b"abcdef", b"ghi", 2).await,
+            AppendResult::Err {
+                error: AppendError::Other { .. }
+            }
+        ));
+
+        upload_chunks(opts, id, b"ghi", 2).await;
+        complete(opts, id).await;
+
+        let status = status(opts, id).await;
+        assert_eq!(status.state, UploadState::Completed);
+        assert_eq!(status.size, 9);
+        assert_eq!(status.name, "测试 1.txt");
+        assert_eq!(status.content_type, "text/plain");
+
+        let resp = warp::test::request()
+            .path(&format!("/{}.txt", id))
+            .reply(&routes(opts.clone()))
+            .await;
+
+        assert_eq!(resp.body().as_ref(), b"abcdefghi");
+        assert_eq!(resp.headers()[CONTENT_TYPE], "text/plain");
+        assert_eq!(
+            resp.headers()[CONTENT_DISPOSITION],
+            "inline; filename*=UTF-8''%E6%B5%8B%E8%AF%95%201.txt"
+        );
+    }
+
+    #[tokio::test]
+    async fn aborting_an_append_keeps_the_file() {
+        let opts = &opts("abort-append");
+
+        let id = initialize(opts, b"abcdef", 4).await;
+        upload_chunks(opts, id, b"abcdef", 4).await;
+        complete(opts, id).await;
+
+        assert!(matches!(
+            append(opts, id, b"abcdef", b"ghi", 2).await,
+            AppendResult::Ok
+        ));
+        upload_chunks(opts, id, b"gh", 2).await;
+
+        let resp = warp::test::request()
+            .method("DELETE")
+            .path(&format!("/file/{}", id))
+            .reply(&routes(opts.clone()))
+            .await;
+        assert!(matches!(
+            serde_json::from_slice::<AbortResult>(resp.body()).unwrap(),
+            AbortResult::Ok
+        ));
+
+        let status = status(opts, id).await;
+        assert_eq!(status.state, UploadState::Completed);
+        assert_eq!(status.size, 6);
+        assert_eq!(status.name, "测试 1.txt");
+
+        // 放弃后可以重新追加
+        assert!(matches!(
+            append(opts, id, b"abcdef", b"ghi", 2).await,
+            AppendResult::Ok
+        ));
+    }
+
+    #[tokio::test]
+    async fn failed_merges_restore_the_target() {
+        let opts = opts("merge");
+        let id = Uuid::new_v4();
+        let chunk_dir = opts.temp_dir.join(id.to_string());
+        std::fs::create_dir_all(&chunk_dir).unwrap();
+
+        let target = target_path(&opts.static_dir, id, "txt");
+        std::fs::write(&target, b"abc").unwrap();
+
+        // 第二个分片不存在，合并到一半失败
+        std::fs::write(chunk_dir.join("0"), b"de").unwrap();
+
+        let meta = Meta {
+            param: InitializeParam {
+                extension: "txt".into(),
+                ..param(4, 2)
+            },
+            offset: 3,
+            state: UploadState::Completing,
+        };
+
+        assert!(merge_chunks(id, &opts.static_dir, &chunk_dir, &meta)
+            .await
+            .is_err());
+        assert_eq!(std::fs::read(&target).unwrap(), b"abc");
+
+        // 新上传失败时不留下文件
+        let meta = Meta { offset: 0, ..meta };
+
+        assert!(merge_chunks(id, &opts.static_dir, &chunk_dir, &meta)
+            .await
+            .is_err());
+        assert!(!target.exists());
+    }
+
+    async fn uploaded(opts: &Opts, data: &[u8]) -> Uuid {
+        let id = initialize(opts, data, 4).await;
+        upload_chunks(opts, id, data, 4).await;
+        complete(opts, id).await;
+        id
+    }
+
+    /// 在本地的随机端口上提供服务
+    fn serve(opts: &Opts) -> SocketAddr {
+        let (addr, server) = warp::serve(routes(opts.clone())).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        addr
+    }
+
+    /// 下载的目标文件，与分片目录和静态目录分开
+    fn download_target(opts: &Opts) -> (PathBuf, PathBuf) {
+        let target = opts.temp_dir.parent().unwrap().join("downloaded.txt");
+        let sidecar = opts.temp_dir.parent().unwrap().join("downloaded.txt.chua");
+        (target, sidecar)
+    }
+
+    const DATA: &[u8] = b"0123456789abcdefghij";
+
+    #[tokio::test]
+    async fn head_reports_the_size_and_ranges() {
+        let opts = &opts("head");
+        let id = uploaded(opts, DATA).await;
+
+        let resp = warp::test::request()
+            .method("HEAD")
+            .path(&format!("/{}.txt", id))
+            .reply(&routes(opts.clone()))
+            .await;
+
+        assert_eq!(resp.status(), 200);
+        assert_eq!(resp.headers()["content-length"], "20");
+        assert_eq!(resp.headers()["accept-ranges"], "bytes");
+    }
+
+    #[tokio::test]
+    async fn downloads_in_ranges() {
+        let opts = &opts("download");
+        let id = uploaded(opts, DATA).await;
+        let url = format!("http://{}/{}.txt", serve(opts), id);
+        let (target, sidecar) = download_target(opts);
+
+        let size = download(
+            &url,
+            &target,
+            3,
+            2,
+            Some(&md5_hex(DATA)),
+            &ClientOptions::default(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(size, DATA.len() as u64);
+        assert_eq!(std::fs::read(&target).unwrap(), DATA);
+        assert!(!sidecar.exists());
+    }
+
+    #[tokio::test]
+    async fn download_resumes_from_the_sidecar() {
+        let opts = &opts("download-resume");
+        let id = uploaded(opts, DATA).await;
+        let url = format!("http://{}/{}.txt", serve(opts), id);
+        let (target, sidecar) = download_target(opts);
+
+        // 第一个分片记录为已完成，内容故意与服务端不同，以确认它没有被重新下载
+        let mut partial = b"XYZ".to_vec();
+        partial.resize(DATA.len(), 0);
+        std::fs::write(&target, &partial).unwrap();
+        std::fs::write(&sidecar, "20 3\n0\n").unwrap();
+
+        download(&url, &target, 3, 2, None, &ClientOptions::default())
+            .await
+            .unwrap();
+
+        assert_eq!(std::fs::read(&target).unwrap(), b"XYZ3456789abcdefghij");
+        assert!(!sidecar.exists());
+    }
+
+    #[tokio::test]
+    async fn download_removes_files_with_a_wrong_md5() {
+        let opts = &opts("download-md5");
+        let id = uploaded(opts, DATA).await;
+        let url = format!("http://{}/{}.txt", serve(opts), id);
+        let (target, sidecar) = download_target(opts);
+
+        let mut partial = b"XYZ".to_vec();
+        partial.resize(DATA.len(), 0);
+        std::fs::write(&target, &partial).unwrap();
+        std::fs::write(&sidecar, "20 3\n0\n").unwrap();
+
+        match download(
+            &url,
+            &target,
+            3,
+            2,
+            Some(&md5_hex(DATA)),
+            &ClientOptions::default(),
+        )
+        .await
+        {
+            Err(ChuaError::Md5Mismatch { expected, actual }) => {
+                assert_eq!(expected, md5_hex(DATA));
+                assert_eq!(actual, md5_hex(b"XYZ3456789abcdefghij"));
+            }
+            other => panic!("expected an MD5 mismatch, got {:?}", other),
+        }
+
+        assert!(!target.exists());
+        assert!(!sidecar.exists());
+
+        // 再次下载从头开始
+        download(
+            &url,
+            &target,
+            3,
+            2,
+            Some(&md5_hex(DATA)),
+            &ClientOptions::default(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(std::fs::read(&target).unwrap(), DATA);
+    }
+
+    #[test]
+    fn ranges_merge_consecutive_flags() {
+        let flags = [true, true, false, true, false, false, true];
+
+        assert_eq!(ranges(&flags, true), vec![0..2, 3..4, 6..7]);
+        assert_eq!(ranges(&flags, false), vec![2..3, 4..6]);
+        assert!(ranges(&[], true).is_empty());
+    }
+}
@@ -1,4 +1,7 @@
-use chua::{CompleteResult, InitializeResult, UploadChunkResult};
+use chua::{
+    AbortResult, AppendResult, CompleteResult, InitializeResult, Limits, StatusResult,
+    UploadChunkResult,
+};
 use warp::http::header::CONTENT_TYPE;
 use warp::http::HeaderValue;
 use warp::http::StatusCode;
@@ -35,3 +38,7 @@ macro_rules! impl_reply_for_result {
 impl_reply_for_result!(InitializeResult, InitializeReply);
 impl_reply_for_result!(UploadChunkResult, UploadChunkReply);
 impl_reply_for_result!(CompleteResult, CompleteReply);
+impl_reply_for_result!(AppendResult, AppendReply);
+impl_reply_for_result!(Limits, LimitsReply);
+impl_reply_for_result!(StatusResult, StatusReply);
+impl_reply_for_result!(AbortResult, AbortReply);
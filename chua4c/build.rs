@@ -0,0 +1,39 @@
+use std::env;
+use std::path::{Path, PathBuf};
+
+/// 由 cbindgen 生成 `chua.h`。平时只写到 `OUT_DIR`，设置了 `CHUA4C_GENERATE_HEADER` 时
+/// 才更新源码中的 `include/chua.h`，避免每次构建都改动工作区
+fn main() {
+    let crate_dir = env::var("CARGO_MANIFEST_DIR").unwrap();
+    let crate_dir = Path::new(&crate_dir);
+
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+    println!("cargo:rerun-if-changed=src");
+    println!("cargo:rerun-if-env-changed=CHUA4C_GENERATE_HEADER");
+
+    let config = match cbindgen::Config::from_file(crate_dir.join("cbindgen.toml")) {
+        Ok(config) => config,
+        Err(e) => panic!("cannot read cbindgen.toml: {}", e),
+    };
+
+    // 直接从 lib.rs 开始解析（会跟随 mod 声明），不需要执行 cargo metadata
+    let bindings = cbindgen::Builder::new()
+        .with_config(config)
+        .with_src(crate_dir.join("src").join("lib.rs"))
+        .generate();
+
+    let bindings = match bindings {
+        Ok(bindings) => bindings,
+        Err(e) => {
+            println!("cargo:warning=cannot generate chua.h: {}", e);
+            return;
+        }
+    };
+
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+    bindings.write_to_file(out_dir.join("chua.h"));
+
+    if env::var_os("CHUA4C_GENERATE_HEADER").is_some() {
+        bindings.write_to_file(crate_dir.join("include").join("chua.h"));
+    }
+}
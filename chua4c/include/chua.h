@@ -0,0 +1,173 @@
+/* chua4c: 欻(chua) 文件分片上传的 C 接口 */
+
+#ifndef CHUA_H
+#define CHUA_H
+
+/* 由 cbindgen 生成，不要手动修改 */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// 文件ID 的字符串形式（含结尾的 NUL）占用的字节数
+#define CHUA_ID_SIZE 37
+
+// 接口的返回值，不是 `CHUA_STATUS_OK` 时可以用 `chua_last_error_message` 取得错误信息
+typedef enum ChuaStatus {
+  // 成功
+  CHUA_STATUS_OK = 0,
+  // 参数不合法，如空指针、不是 UTF-8 的字符串或者输出缓冲区太小
+  CHUA_STATUS_INVALID_ARGUMENT = 1,
+  // 读取本地文件失败
+  CHUA_STATUS_IO = 2,
+  // 网络请求失败
+  CHUA_STATUS_HTTP = 3,
+  // 服务端拒绝初始化，如文件或分片太大
+  CHUA_STATUS_INITIALIZE = 4,
+  // 服务端拒绝分片
+  CHUA_STATUS_UPLOAD_CHUNK = 5,
+  // 服务端无法完成上传，如缺少分片或 MD5 不一致
+  CHUA_STATUS_COMPLETE = 6,
+  // 上传被取消
+  CHUA_STATUS_ABORTED = 7,
+  // 其它错误
+  CHUA_STATUS_OTHER = 8,
+  // 内部错误（panic）
+  CHUA_STATUS_PANIC = 9,
+} ChuaStatus;
+
+// 上传的目标服务端和 HTTP 客户端配置，可以在多个线程中同时使用
+typedef struct ChuaClient ChuaClient;
+
+// 一次异步上传的句柄，用 `chua_upload_cancel` 取消，用 `chua_upload_free` 释放
+typedef struct ChuaUpload ChuaUpload;
+
+// HTTP 客户端的配置，可以用 `chua_client_options_default` 初始化
+typedef struct ChuaClientOptions {
+  // 单个请求的超时时间（毫秒），为 0 时使用默认值（20 秒）
+  uint64_t timeout_millis;
+  // HTTP 代理，如 `http://proxy.example.com:8080`，为 NULL 时使用系统代理
+  const char *proxy;
+  // 额外信任的根证书（PEM 格式），共 `root_certificates_len` 个
+  const char *const *root_certificates;
+  // `root_certificates` 的个数
+  size_t root_certificates_len;
+  // 客户端证书链和私钥（PEM 格式），用于双向 TLS，可以为 NULL
+  const char *identity;
+} ChuaClientOptions;
+
+// 一次上传的参数，可以用 `chua_upload_options_default` 初始化
+typedef struct ChuaUploadOptions {
+  // 分片大小，为 0 时由服务端的限制决定
+  uint64_t chunk_size;
+  // 并行数，为 0 时使用 CPU 核数
+  uint32_t parallel;
+  // 提交给服务端的文件名，为 NULL 时使用本地文件名
+  const char *name;
+  // MIME 类型，为 NULL 时按扩展名推断
+  const char *content_type;
+} ChuaUploadOptions;
+
+// 进度回调，参数依次为 `user_data`、已上传的字节数和总字节数
+typedef void (*ChuaProgressCallback)(void *user_data, uint64_t loaded, uint64_t total);
+
+// 上传结束时的回调，参数依次为 `user_data`、结果、文件ID 和错误信息。
+// 成功时 `error_message` 为 NULL，失败时 `id` 为 NULL，两个字符串只在回调期间有效
+typedef void (*ChuaCompleteCallback)(void *user_data,
+                                     enum ChuaStatus status,
+                                     const char *id,
+                                     const char *error_message);
+
+// 异步上传的回调。回调在内部的线程中执行，不能阻塞太久
+typedef struct ChuaCallbacks {
+  // 进度回调，可以为 NULL
+  ChuaProgressCallback on_progress;
+  // 上传结束时的回调，只调用一次，可以为 NULL
+  ChuaCompleteCallback on_complete;
+  // 原样传给回调
+  void *user_data;
+} ChuaCallbacks;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// 创建客户端，成功时写入 `*out`，用完后用 `chua_client_free` 释放。
+// `options` 为 NULL 时使用默认配置
+//
+// # Safety
+//
+// `base_url` 必须指向以 NUL 结尾的字符串，`options` 必须为 NULL 或指向有效的 `ChuaClientOptions`，
+// `out` 必须指向可写的 `ChuaClient*`
+enum ChuaStatus chua_client_new(const char *base_url,
+                                const struct ChuaClientOptions *options,
+                                struct ChuaClient **out);
+
+// 释放客户端，`client` 可以为 NULL
+//
+// # Safety
+//
+// `client` 必须是 `chua_client_new` 创建且尚未释放的
+void chua_client_free(struct ChuaClient *client);
+
+// 上传文件 `path`，阻塞到上传完成，不想阻塞时用 `chua_upload_start`。成功时把文件ID 以字符串形式写入 `out_id`，
+// 缓冲区至少要 `CHUA_ID_SIZE` 字节。`options` 为 NULL 时使用默认参数
+//
+// # Safety
+//
+// `client` 必须是有效的客户端，`path` 必须指向以 NUL 结尾的字符串，`options` 必须为 NULL
+// 或指向有效的 `ChuaUploadOptions`，`out_id` 必须指向至少 `out_id_len` 字节的可写缓冲区
+enum ChuaStatus chua_upload(const struct ChuaClient *client,
+                            const char *path,
+                            const struct ChuaUploadOptions *options,
+                            char *out_id,
+                            size_t out_id_len);
+
+// 当前线程上一次失败的错误信息（UTF-8），没有时返回 NULL。
+// 返回的指针在当前线程下一次调用失败前有效，不需要释放
+const char *chua_last_error_message(void);
+
+// 默认的客户端配置
+struct ChuaClientOptions chua_client_options_default(void);
+
+// 默认的上传参数
+struct ChuaUploadOptions chua_upload_options_default(void);
+
+// 开始上传文件 `path`，立即返回，成功时把句柄写入 `*out`。上传在内部的线程中进行，
+// 通过 `callbacks` 报告进度和结果。`options` 为 NULL 时使用默认参数。
+//
+// 返回 `CHUA_STATUS_OK` 时 `on_complete` 一定会被调用一次；返回其它值时不会调用任何回调
+//
+// # Safety
+//
+// `client` 必须是有效的客户端，`path` 必须指向以 NUL 结尾的字符串，`options` 必须为 NULL
+// 或指向有效的 `ChuaUploadOptions`，`callbacks` 必须为 NULL 或指向有效的 `ChuaCallbacks`，
+// `out` 必须指向可写的 `ChuaUpload*`。`user_data` 要在 `on_complete` 被调用前保持有效
+enum ChuaStatus chua_upload_start(const struct ChuaClient *client,
+                                  const char *path,
+                                  const struct ChuaUploadOptions *options,
+                                  const struct ChuaCallbacks *callbacks,
+                                  struct ChuaUpload **out);
+
+// 取消上传，服务端会丢弃已上传的分片，`on_complete` 随后以 `CHUA_STATUS_ABORTED` 被调用。
+// 上传已经结束时什么也不做
+//
+// # Safety
+//
+// `upload` 必须是 `chua_upload_start` 创建且尚未释放的
+enum ChuaStatus chua_upload_cancel(const struct ChuaUpload *upload);
+
+// 释放句柄，`upload` 可以为 NULL。释放不会取消上传，回调仍会照常被调用
+//
+// # Safety
+//
+// `upload` 必须是 `chua_upload_start` 创建且尚未释放的
+void chua_upload_free(struct ChuaUpload *upload);
+
+#ifdef __cplusplus
+} // extern "C"
+#endif // __cplusplus
+
+#endif /* CHUA_H */
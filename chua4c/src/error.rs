@@ -0,0 +1,136 @@
+use chua::ChuaError;
+use std::any::Any;
+use std::cell::RefCell;
+use std::ffi::CString;
+use std::fmt::Display;
+use std::os::raw::c_char;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::ptr;
+
+/// 接口的返回值，不是 `CHUA_STATUS_OK` 时可以用 `chua_last_error_message` 取得错误信息
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChuaStatus {
+    /// 成功
+    Ok = 0,
+
+    /// 参数不合法，如空指针、不是 UTF-8 的字符串或者输出缓冲区太小
+    InvalidArgument = 1,
+
+    /// 读取本地文件失败
+    Io = 2,
+
+    /// 网络请求失败
+    Http = 3,
+
+    /// 服务端拒绝初始化，如文件或分片太大
+    Initialize = 4,
+
+    /// 服务端拒绝分片
+    UploadChunk = 5,
+
+    /// 服务端无法完成上传，如缺少分片或 MD5 不一致
+    Complete = 6,
+
+    /// 上传被取消
+    Aborted = 7,
+
+    /// 其它错误
+    Other = 8,
+
+    /// 内部错误（panic）
+    Panic = 9,
+}
+
+impl From<&ChuaError> for ChuaStatus {
+    fn from(e: &ChuaError) -> Self {
+        match e {
+            ChuaError::Io(_) => Self::Io,
+            ChuaError::Http(_) | ChuaError::HttpStatus { .. } => Self::Http,
+            ChuaError::Url(_) => Self::InvalidArgument,
+            ChuaError::Initialize(_) => Self::Initialize,
+            ChuaError::UploadChunk(_) => Self::UploadChunk,
+            ChuaError::Complete(_) => Self::Complete,
+            ChuaError::Aborted => Self::Aborted,
+            _ => Self::Other,
+        }
+    }
+}
+
+/// 失败的原因，由接口转换成 `ChuaStatus` 并记录错误信息
+pub(crate) struct Error {
+    status: ChuaStatus,
+    message: String,
+}
+
+impl Error {
+    pub fn invalid_argument(message: impl Display) -> Self {
+        Self {
+            status: ChuaStatus::InvalidArgument,
+            message: message.to_string(),
+        }
+    }
+}
+
+impl From<ChuaError> for Error {
+    fn from(e: ChuaError) -> Self {
+        Self {
+            status: ChuaStatus::from(&e),
+            message: e.to_string(),
+        }
+    }
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// 执行 `f`，失败或 panic 时记录错误信息并返回对应的 `ChuaStatus`，panic 不会越过 C 的边界
+pub(crate) fn ffi(f: impl FnOnce() -> Result<(), Error>) -> ChuaStatus {
+    let error = match catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(())) => return ChuaStatus::Ok,
+        Ok(Err(e)) => e,
+        Err(panic) => Error {
+            status: ChuaStatus::Panic,
+            message: panic_message(&*panic),
+        },
+    };
+
+    set_last_error(&error.message);
+
+    error.status
+}
+
+/// 取出 panic 时的信息
+pub(crate) fn panic_message(panic: &(dyn Any + Send)) -> String {
+    match panic.downcast_ref::<&str>() {
+        Some(message) => message.to_string(),
+        None => match panic.downcast_ref::<String>() {
+            Some(message) => message.clone(),
+            None => "panicked".to_string(),
+        },
+    }
+}
+
+fn set_last_error(message: &str) {
+    // 错误信息中不会有 NUL，万一有就截断
+    let message = match CString::new(message) {
+        Ok(message) => message,
+        Err(e) => {
+            let end = e.nul_position();
+            CString::new(&message.as_bytes()[..end]).unwrap_or_default()
+        }
+    };
+
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+}
+
+/// 当前线程上一次失败的错误信息（UTF-8），没有时返回 NULL。
+/// 返回的指针在当前线程下一次调用失败前有效，不需要释放
+#[no_mangle]
+pub extern "C" fn chua_last_error_message() -> *const c_char {
+    LAST_ERROR.with(|last| match &*last.borrow() {
+        Some(message) => message.as_ptr(),
+        None => ptr::null(),
+    })
+}
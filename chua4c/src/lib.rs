@@ -1,7 +1,291 @@
+mod error;
+mod options;
+mod upload;
+
+use chua::{blocking, ClientOptions};
+use error::{ffi, Error};
+use options::string;
+use std::os::raw::c_char;
+use std::ptr;
+use url::Url;
+
+pub use error::{chua_last_error_message, ChuaStatus};
+pub use options::{
+    chua_client_options_default, chua_upload_options_default, ChuaClientOptions, ChuaUploadOptions,
+};
+pub use upload::{
+    chua_upload_cancel, chua_upload_free, chua_upload_start, ChuaCallbacks, ChuaCompleteCallback,
+    ChuaProgressCallback, ChuaUpload,
+};
+
+/// 文件ID 的字符串形式（含结尾的 NUL）占用的字节数
+pub const CHUA_ID_SIZE: usize = 37;
+
+/// 上传的目标服务端和 HTTP 客户端配置，可以在多个线程中同时使用
+pub struct ChuaClient {
+    base_url: Url,
+    options: ClientOptions,
+}
+
+/// 创建客户端，成功时写入 `*out`，用完后用 `chua_client_free` 释放。
+/// `options` 为 NULL 时使用默认配置
+///
+/// # Safety
+///
+/// `base_url` 必须指向以 NUL 结尾的字符串，`options` 必须为 NULL 或指向有效的 `ChuaClientOptions`，
+/// `out` 必须指向可写的 `ChuaClient*`
+#[no_mangle]
+pub unsafe extern "C" fn chua_client_new(
+    base_url: *const c_char,
+    options: *const ChuaClientOptions,
+    out: *mut *mut ChuaClient,
+) -> ChuaStatus {
+    ffi(|| {
+        if out.is_null() {
+            return Err(Error::invalid_argument("out must not be NULL"));
+        }
+
+        let base_url = string(base_url, "base_url")?;
+        let base_url = Url::parse(&base_url).map_err(Error::invalid_argument)?;
+
+        let options = match options.as_ref() {
+            Some(options) => options.to_client_options()?,
+            None => ClientOptions::default(),
+        };
+
+        *out = Box::into_raw(Box::new(ChuaClient { base_url, options }));
+
+        Ok(())
+    })
+}
+
+/// 释放客户端，`client` 可以为 NULL
+///
+/// # Safety
+///
+/// `client` 必须是 `chua_client_new` 创建且尚未释放的
+#[no_mangle]
+pub unsafe extern "C" fn chua_client_free(client: *mut ChuaClient) {
+    if !client.is_null() {
+        drop(Box::from_raw(client));
+    }
+}
+
+/// 上传文件 `path`，阻塞到上传完成，不想阻塞时用 `chua_upload_start`。成功时把文件ID 以字符串形式写入 `out_id`，
+/// 缓冲区至少要 `CHUA_ID_SIZE` 字节。`options` 为 NULL 时使用默认参数
+///
+/// # Safety
+///
+/// `client` 必须是有效的客户端，`path` 必须指向以 NUL 结尾的字符串，`options` 必须为 NULL
+/// 或指向有效的 `ChuaUploadOptions`，`out_id` 必须指向至少 `out_id_len` 字节的可写缓冲区
+#[no_mangle]
+pub unsafe extern "C" fn chua_upload(
+    client: *const ChuaClient,
+    path: *const c_char,
+    options: *const ChuaUploadOptions,
+    out_id: *mut c_char,
+    out_id_len: usize,
+) -> ChuaStatus {
+    ffi(|| {
+        let client = match client.as_ref() {
+            Some(client) => client,
+            None => return Err(Error::invalid_argument("client must not be NULL")),
+        };
+
+        if out_id.is_null() || out_id_len < CHUA_ID_SIZE {
+            return Err(Error::invalid_argument(format!(
+                "out_id must have at least {} bytes",
+                CHUA_ID_SIZE
+            )));
+        }
+
+        let path = string(path, "path")?;
+
+        let options = match options.as_ref() {
+            Some(options) => *options,
+            None => chua_upload_options_default(),
+        };
+
+        let info = options.to_file_info()?;
+
+        let id = blocking::upload_with_info(
+            client.base_url.clone(),
+            path,
+            options.chunk_size,
+            options.parallel as usize,
+            info,
+            &client.options,
+        )?;
+
+        write_id(&id.to_string(), out_id);
+
+        Ok(())
+    })
+}
+
+/// 把文件ID 和结尾的 NUL 写入 `out`
+///
+/// # Safety
+///
+/// `out` 必须指向至少 `CHUA_ID_SIZE` 字节的可写缓冲区
+unsafe fn write_id(id: &str, out: *mut c_char) {
+    ptr::copy_nonoverlapping(id.as_ptr() as *const c_char, out, id.len());
+    *out.add(id.len()) = 0;
+}
+
 #[cfg(test)]
 mod tests {
+    use super::*;
+    use std::ffi::{CStr, CString};
+
+    fn last_error() -> String {
+        let message = chua_last_error_message();
+        assert!(!message.is_null());
+        unsafe { CStr::from_ptr(message) }
+            .to_str()
+            .unwrap()
+            .to_string()
+    }
+
+    /// 指向一个没有服务监听的地址，检查参数时就应失败，不会发出请求
+    fn client() -> *mut ChuaClient {
+        let url = CString::new("http://127.0.0.1:9/").unwrap();
+        let mut client = ptr::null_mut();
+
+        let status = unsafe { chua_client_new(url.as_ptr(), ptr::null(), &mut client) };
+        assert_eq!(status, ChuaStatus::Ok);
+        assert!(!client.is_null());
+
+        client
+    }
+
+    #[test]
+    fn no_error_message_before_a_failure() {
+        // 错误信息是线程局部的，新线程中还没有
+        let message = std::thread::spawn(|| chua_last_error_message() as usize)
+            .join()
+            .unwrap();
+
+        assert_eq!(message, 0);
+    }
+
+    #[test]
+    fn client_new_rejects_bad_arguments() {
+        let mut client = ptr::null_mut();
+
+        let url = CString::new("not a url").unwrap();
+        let status = unsafe { chua_client_new(url.as_ptr(), ptr::null(), &mut client) };
+        assert_eq!(status, ChuaStatus::InvalidArgument);
+        assert_eq!(last_error(), "relative URL without a base");
+        assert!(client.is_null());
+
+        let status = unsafe { chua_client_new(ptr::null(), ptr::null(), &mut client) };
+        assert_eq!(status, ChuaStatus::InvalidArgument);
+        assert_eq!(last_error(), "base_url must not be NULL");
+
+        let url = CString::new("http://127.0.0.1:9/").unwrap();
+        let status = unsafe { chua_client_new(url.as_ptr(), ptr::null(), ptr::null_mut()) };
+        assert_eq!(status, ChuaStatus::InvalidArgument);
+        assert_eq!(last_error(), "out must not be NULL");
+    }
+
+    #[test]
+    fn client_new_rejects_bad_options() {
+        let url = CString::new("http://127.0.0.1:9/").unwrap();
+        let invalid = b"\xff\0".as_ptr() as *const c_char;
+        let mut client = ptr::null_mut();
+
+        let options = ChuaClientOptions {
+            proxy: invalid,
+            ..chua_client_options_default()
+        };
+        let status = unsafe { chua_client_new(url.as_ptr(), &options, &mut client) };
+        assert_eq!(status, ChuaStatus::InvalidArgument);
+        assert_eq!(last_error(), "proxy is not valid UTF-8");
+
+        let certificates = [invalid];
+        let options = ChuaClientOptions {
+            root_certificates: certificates.as_ptr(),
+            root_certificates_len: certificates.len(),
+            ..chua_client_options_default()
+        };
+        let status = unsafe { chua_client_new(url.as_ptr(), &options, &mut client) };
+        assert_eq!(status, ChuaStatus::InvalidArgument);
+        assert_eq!(last_error(), "root_certificates is not valid UTF-8");
+
+        assert!(client.is_null());
+    }
+
+    #[test]
+    fn upload_checks_path() {
+        let client = client();
+        let mut id = [0 as c_char; CHUA_ID_SIZE];
+
+        let status =
+            unsafe { chua_upload(client, ptr::null(), ptr::null(), id.as_mut_ptr(), id.len()) };
+        assert_eq!(status, ChuaStatus::InvalidArgument);
+        assert_eq!(last_error(), "path must not be NULL");
+
+        let path = CString::new("/nonexistent/chua4c-test-file").unwrap();
+        let status = unsafe {
+            chua_upload(
+                client,
+                path.as_ptr(),
+                ptr::null(),
+                id.as_mut_ptr(),
+                id.len(),
+            )
+        };
+        assert_eq!(status, ChuaStatus::Other);
+        assert_eq!(last_error(), "The path is not pointing a regular file");
+
+        unsafe { chua_client_free(client) };
+    }
+
+    #[test]
+    fn upload_requires_room_for_the_id() {
+        let client = client();
+        let path = CString::new("/nonexistent/chua4c-test-file").unwrap();
+        let mut id = [0 as c_char; CHUA_ID_SIZE - 1];
+
+        let status = unsafe {
+            chua_upload(
+                client,
+                path.as_ptr(),
+                ptr::null(),
+                id.as_mut_ptr(),
+                id.len(),
+            )
+        };
+        assert_eq!(status, ChuaStatus::InvalidArgument);
+        assert_eq!(last_error(), "out_id must have at least 37 bytes");
+
+        let status = unsafe {
+            chua_upload(
+                client,
+                path.as_ptr(),
+                ptr::null(),
+                ptr::null_mut(),
+                CHUA_ID_SIZE,
+            )
+        };
+        assert_eq!(status, ChuaStatus::InvalidArgument);
+
+        unsafe { chua_client_free(client) };
+    }
+
     #[test]
-    fn it_works() {
-        assert_eq!(2 + 2, 4);
+    fn write_id_fills_the_buffer_with_a_c_string() {
+        let id = "67e55044-10b1-426f-9247-bb680e5fe0c8";
+        assert_eq!(id.len() + 1, CHUA_ID_SIZE);
+
+        let mut out = [b'x' as c_char; CHUA_ID_SIZE];
+        unsafe { write_id(id, out.as_mut_ptr()) };
+
+        assert_eq!(out[CHUA_ID_SIZE - 1], 0);
+        assert_eq!(
+            unsafe { CStr::from_ptr(out.as_ptr()) }.to_str().unwrap(),
+            id
+        );
     }
 }
@@ -0,0 +1,144 @@
+use crate::error::Error;
+use chua::{ClientOptions, FileInfo};
+use std::ffi::CStr;
+use std::os::raw::c_char;
+use std::slice;
+use std::time::Duration;
+
+/// HTTP 客户端的配置，可以用 `chua_client_options_default` 初始化
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct ChuaClientOptions {
+    /// 单个请求的超时时间（毫秒），为 0 时使用默认值（20 秒）
+    pub timeout_millis: u64,
+
+    /// HTTP 代理，如 `http://proxy.example.com:8080`，为 NULL 时使用系统代理
+    pub proxy: *const c_char,
+
+    /// 额外信任的根证书（PEM 格式），共 `root_certificates_len` 个
+    pub root_certificates: *const *const c_char,
+
+    /// `root_certificates` 的个数
+    pub root_certificates_len: usize,
+
+    /// 客户端证书链和私钥（PEM 格式），用于双向 TLS，可以为 NULL
+    pub identity: *const c_char,
+}
+
+/// 一次上传的参数，可以用 `chua_upload_options_default` 初始化
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct ChuaUploadOptions {
+    /// 分片大小，为 0 时由服务端的限制决定
+    pub chunk_size: u64,
+
+    /// 并行数，为 0 时使用 CPU 核数
+    pub parallel: u32,
+
+    /// 提交给服务端的文件名，为 NULL 时使用本地文件名
+    pub name: *const c_char,
+
+    /// MIME 类型，为 NULL 时按扩展名推断
+    pub content_type: *const c_char,
+}
+
+/// 默认的客户端配置
+#[no_mangle]
+pub extern "C" fn chua_client_options_default() -> ChuaClientOptions {
+    ChuaClientOptions {
+        timeout_millis: 0,
+        proxy: std::ptr::null(),
+        root_certificates: std::ptr::null(),
+        root_certificates_len: 0,
+        identity: std::ptr::null(),
+    }
+}
+
+/// 默认的上传参数
+#[no_mangle]
+pub extern "C" fn chua_upload_options_default() -> ChuaUploadOptions {
+    ChuaUploadOptions {
+        chunk_size: 0,
+        parallel: 0,
+        name: std::ptr::null(),
+        content_type: std::ptr::null(),
+    }
+}
+
+impl ChuaClientOptions {
+    /// # Safety
+    ///
+    /// 所有指针必须为 NULL 或指向以 NUL 结尾的字符串
+    pub(crate) unsafe fn to_client_options(self) -> Result<ClientOptions, Error> {
+        let mut options = ClientOptions::default();
+
+        if self.timeout_millis > 0 {
+            options.timeout = Duration::from_millis(self.timeout_millis);
+        }
+
+        options.proxy = optional_string(self.proxy, "proxy")?;
+        options.identity = optional_string(self.identity, "identity")?.map(String::into_bytes);
+
+        if !self.root_certificates.is_null() {
+            let certificates =
+                slice::from_raw_parts(self.root_certificates, self.root_certificates_len);
+            for certificate in certificates {
+                if let Some(pem) = optional_string(*certificate, "root_certificates")? {
+                    options.root_certificates.push(pem.into_bytes());
+                }
+            }
+        }
+
+        Ok(options)
+    }
+}
+
+impl ChuaUploadOptions {
+    /// # Safety
+    ///
+    /// 所有指针必须为 NULL 或指向以 NUL 结尾的字符串
+    pub(crate) unsafe fn to_file_info(self) -> Result<FileInfo, Error> {
+        Ok(FileInfo {
+            name: optional_string(self.name, "name")?,
+            content_type: optional_string(self.content_type, "content_type")?,
+            ..FileInfo::default()
+        })
+    }
+}
+
+/// 读取以 NUL 结尾的 UTF-8 字符串，为 NULL 时返回错误
+///
+/// # Safety
+///
+/// `s` 必须为 NULL 或指向以 NUL 结尾的字符串
+pub(crate) unsafe fn string(s: *const c_char, name: &str) -> Result<String, Error> {
+    match optional_string(s, name)? {
+        Some(s) => Ok(s),
+        None => Err(Error::invalid_argument(format!(
+            "{} must not be NULL",
+            name
+        ))),
+    }
+}
+
+/// 读取以 NUL 结尾的 UTF-8 字符串，为 NULL 时返回 `None`
+///
+/// # Safety
+///
+/// `s` 必须为 NULL 或指向以 NUL 结尾的字符串
+pub(crate) unsafe fn optional_string(
+    s: *const c_char,
+    name: &str,
+) -> Result<Option<String>, Error> {
+    if s.is_null() {
+        return Ok(None);
+    }
+
+    match CStr::from_ptr(s).to_str() {
+        Ok(s) => Ok(Some(s.to_string())),
+        Err(_) => Err(Error::invalid_argument(format!(
+            "{} is not valid UTF-8",
+            name
+        ))),
+    }
+}
@@ -0,0 +1,288 @@
+use crate::error::{ffi, panic_message, ChuaStatus, Error};
+use crate::options::{chua_upload_options_default, string, ChuaUploadOptions};
+use crate::ChuaClient;
+use chua::{blocking, upload_cancelable, CancelToken, ChuaResult, UploadEvent, UploadEvents};
+use futures::{Future, FutureExt, StreamExt};
+use std::ffi::CString;
+use std::os::raw::{c_char, c_void};
+use std::panic::AssertUnwindSafe;
+use std::ptr;
+use uuid::Uuid;
+
+/// 进度回调，参数依次为 `user_data`、已上传的字节数和总字节数
+pub type ChuaProgressCallback =
+    Option<extern "C" fn(user_data: *mut c_void, loaded: u64, total: u64)>;
+
+/// 上传结束时的回调，参数依次为 `user_data`、结果、文件ID 和错误信息。
+/// 成功时 `error_message` 为 NULL，失败时 `id` 为 NULL，两个字符串只在回调期间有效
+pub type ChuaCompleteCallback = Option<
+    extern "C" fn(
+        user_data: *mut c_void,
+        status: ChuaStatus,
+        id: *const c_char,
+        error_message: *const c_char,
+    ),
+>;
+
+/// 异步上传的回调。回调在内部的线程中执行，不能阻塞太久
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct ChuaCallbacks {
+    /// 进度回调，可以为 NULL
+    pub on_progress: ChuaProgressCallback,
+
+    /// 上传结束时的回调，只调用一次，可以为 NULL
+    pub on_complete: ChuaCompleteCallback,
+
+    /// 原样传给回调
+    pub user_data: *mut c_void,
+}
+
+// `user_data` 由调用方保证可以在其它线程中使用
+unsafe impl Send for ChuaCallbacks {}
+
+impl ChuaCallbacks {
+    fn progress(&self, loaded: u64, total: u64) {
+        if let Some(on_progress) = self.on_progress {
+            on_progress(self.user_data, loaded, total);
+        }
+    }
+
+    fn complete(&self, result: ChuaResult<Uuid>) {
+        let on_complete = match self.on_complete {
+            Some(on_complete) => on_complete,
+            None => return,
+        };
+
+        match result {
+            Ok(id) => {
+                let id = CString::new(id.to_string()).unwrap_or_default();
+                on_complete(self.user_data, ChuaStatus::Ok, id.as_ptr(), ptr::null());
+            }
+            Err(e) => self.fail(ChuaStatus::from(&e), &e.to_string()),
+        }
+    }
+
+    fn fail(&self, status: ChuaStatus, message: &str) {
+        if let Some(on_complete) = self.on_complete {
+            let message = CString::new(message).unwrap_or_default();
+            on_complete(self.user_data, status, ptr::null(), message.as_ptr());
+        }
+    }
+}
+
+/// 一次异步上传的句柄，用 `chua_upload_cancel` 取消，用 `chua_upload_free` 释放
+pub struct ChuaUpload {
+    cancel: CancelToken,
+}
+
+/// 开始上传文件 `path`，立即返回，成功时把句柄写入 `*out`。上传在内部的线程中进行，
+/// 通过 `callbacks` 报告进度和结果。`options` 为 NULL 时使用默认参数。
+///
+/// 返回 `CHUA_STATUS_OK` 时 `on_complete` 一定会被调用一次；返回其它值时不会调用任何回调
+///
+/// # Safety
+///
+/// `client` 必须是有效的客户端，`path` 必须指向以 NUL 结尾的字符串，`options` 必须为 NULL
+/// 或指向有效的 `ChuaUploadOptions`，`callbacks` 必须为 NULL 或指向有效的 `ChuaCallbacks`，
+/// `out` 必须指向可写的 `ChuaUpload*`。`user_data` 要在 `on_complete` 被调用前保持有效
+#[no_mangle]
+pub unsafe extern "C" fn chua_upload_start(
+    client: *const ChuaClient,
+    path: *const c_char,
+    options: *const ChuaUploadOptions,
+    callbacks: *const ChuaCallbacks,
+    out: *mut *mut ChuaUpload,
+) -> ChuaStatus {
+    ffi(|| {
+        let client = match client.as_ref() {
+            Some(client) => client,
+            None => return Err(Error::invalid_argument("client must not be NULL")),
+        };
+
+        if out.is_null() {
+            return Err(Error::invalid_argument("out must not be NULL"));
+        }
+
+        let path = string(path, "path")?;
+
+        let options = match options.as_ref() {
+            Some(options) => *options,
+            None => chua_upload_options_default(),
+        };
+
+        let callbacks = match callbacks.as_ref() {
+            Some(callbacks) => *callbacks,
+            None => ChuaCallbacks {
+                on_progress: None,
+                on_complete: None,
+                user_data: ptr::null_mut(),
+            },
+        };
+
+        let info = options.to_file_info()?;
+
+        let runtime = blocking::handle()?;
+
+        let cancel = CancelToken::new();
+
+        let (future, events) = upload_cancelable(
+            client.base_url.clone(),
+            path,
+            options.chunk_size,
+            options.parallel as usize,
+            info,
+            client.options.clone(),
+            cancel.clone(),
+        );
+
+        runtime.spawn(run(future, report(events, callbacks), callbacks));
+
+        *out = Box::into_raw(Box::new(ChuaUpload { cancel }));
+
+        Ok(())
+    })
+}
+
+/// 取消上传，服务端会丢弃已上传的分片，`on_complete` 随后以 `CHUA_STATUS_ABORTED` 被调用。
+/// 上传已经结束时什么也不做
+///
+/// # Safety
+///
+/// `upload` 必须是 `chua_upload_start` 创建且尚未释放的
+#[no_mangle]
+pub unsafe extern "C" fn chua_upload_cancel(upload: *const ChuaUpload) -> ChuaStatus {
+    ffi(|| match upload.as_ref() {
+        Some(upload) => {
+            upload.cancel.cancel();
+            Ok(())
+        }
+        None => Err(Error::invalid_argument("upload must not be NULL")),
+    })
+}
+
+/// 释放句柄，`upload` 可以为 NULL。释放不会取消上传，回调仍会照常被调用
+///
+/// # Safety
+///
+/// `upload` 必须是 `chua_upload_start` 创建且尚未释放的
+#[no_mangle]
+pub unsafe extern "C" fn chua_upload_free(upload: *mut ChuaUpload) {
+    if !upload.is_null() {
+        drop(Box::from_raw(upload));
+    }
+}
+
+/// 执行上传并报告结果。上传中 panic 时以 `CHUA_STATUS_PANIC` 调用 `on_complete`，保证它被调用一次
+async fn run(
+    upload: impl Future<Output = ChuaResult<Uuid>>,
+    report: impl Future<Output = ()>,
+    callbacks: ChuaCallbacks,
+) {
+    let result = AssertUnwindSafe(async {
+        let (result, _) = futures::join!(upload, report);
+        result
+    })
+    .catch_unwind()
+    .await;
+
+    match result {
+        Ok(result) => callbacks.complete(result),
+        Err(panic) => callbacks.fail(ChuaStatus::Panic, &panic_message(&*panic)),
+    }
+}
+
+/// 消费上传事件，把进度报告给回调
+async fn report(mut events: UploadEvents, callbacks: ChuaCallbacks) {
+    let mut loaded = 0;
+    let mut total = 0;
+
+    while let Some(event) = events.next().await {
+        match event {
+            UploadEvent::Initialized { size, .. } => total = size,
+            UploadEvent::Resumed {
+                size,
+                received_size,
+                ..
+            } => {
+                total = size;
+                loaded = received_size;
+            }
+            UploadEvent::ChunkAcknowledged { size, .. } => loaded += size,
+            _ => continue,
+        }
+
+        callbacks.progress(loaded, total);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CStr;
+    use std::sync::Mutex;
+
+    /// `on_complete` 收到的结果：状态、文件ID 和错误信息
+    type Completed = Mutex<Vec<(ChuaStatus, Option<String>, Option<String>)>>;
+
+    extern "C" fn on_complete(
+        user_data: *mut c_void,
+        status: ChuaStatus,
+        id: *const c_char,
+        error_message: *const c_char,
+    ) {
+        let to_string = |s: *const c_char| {
+            if s.is_null() {
+                None
+            } else {
+                Some(unsafe { CStr::from_ptr(s) }.to_str().unwrap().to_string())
+            }
+        };
+
+        let completed = unsafe { &*(user_data as *const Completed) };
+        completed
+            .lock()
+            .unwrap()
+            .push((status, to_string(id), to_string(error_message)));
+    }
+
+    fn callbacks(completed: &Completed) -> ChuaCallbacks {
+        ChuaCallbacks {
+            on_progress: None,
+            on_complete: Some(on_complete),
+            user_data: completed as *const Completed as *mut c_void,
+        }
+    }
+
+    #[test]
+    fn reports_the_uploaded_id() {
+        let completed = Completed::default();
+
+        futures::executor::block_on(run(
+            async { Ok(Uuid::nil()) },
+            async {},
+            callbacks(&completed),
+        ));
+
+        assert_eq!(
+            completed.into_inner().unwrap(),
+            vec![(ChuaStatus::Ok, Some(Uuid::nil().to_string()), None)]
+        );
+    }
+
+    #[test]
+    fn reports_panics_as_failures() {
+        let completed = Completed::default();
+
+        futures::executor::block_on(run(
+            async { panic!("upload exploded") },
+            async {},
+            callbacks(&completed),
+        ));
+
+        assert_eq!(
+            completed.into_inner().unwrap(),
+            vec![(ChuaStatus::Panic, None, Some("upload exploded".to_string()))]
+        );
+    }
+}
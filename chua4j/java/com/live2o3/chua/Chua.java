@@ -10,4 +10,31 @@ public class Chua {
     }
 
     public static native Result<String> upload(String baseUrl, String path, long chunkSize, int parallel);
+
+    public static native Result<String> uploadWithOptions(String baseUrl, String path, long chunkSize, int parallel, ClientOptions options);
+
+    /** Downscales and re-encodes JPEG/PNG images, stripping EXIF/GPS metadata, before uploading. Other files are uploaded as is. */
+    public static native Result<String> uploadImage(String baseUrl, String path, long chunkSize, int parallel, ImageOptions image, ClientOptions options);
+
+    /**
+     * Starts uploading without blocking the calling thread. Progress and the outcome are reported to {@code listener}
+     * on native worker threads. {@code options} may be null.
+     */
+    public static UploadTask uploadAsync(String baseUrl, String path, long chunkSize, int parallel, ClientOptions options, UploadListener listener) {
+        return new UploadTask(startUpload(baseUrl, path, chunkSize, parallel, options, listener));
+    }
+
+    /**
+     * Like {@link #uploadAsync} but uploads an already open file descriptor, e.g. from
+     * {@code ContentResolver.openFileDescriptor(uri, "r").detachFd()}, without copying the file first.
+     * The descriptor is owned and closed by chua4j. Pass -1 as {@code size} when unknown.
+     * The extension of {@code name} is used as the file extension.
+     */
+    public static UploadTask uploadFdAsync(String baseUrl, int fd, long size, String name, long chunkSize, int parallel, ClientOptions options, UploadListener listener) {
+        return new UploadTask(startFdUpload(baseUrl, fd, size, name, chunkSize, parallel, options, listener));
+    }
+
+    private static native long startUpload(String baseUrl, String path, long chunkSize, int parallel, ClientOptions options, UploadListener listener);
+
+    private static native long startFdUpload(String baseUrl, int fd, long size, String name, long chunkSize, int parallel, ClientOptions options, UploadListener listener);
 }
\ No newline at end of file
@@ -0,0 +1,9 @@
+package com.live2o3.chua;
+
+/** Base class of the errors reported by chua4j. Thrown as is for errors without a more specific type. */
+public class ChuaException extends Exception {
+
+    public ChuaException(String message) {
+        super(message);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,19 @@
+package com.live2o3.chua;
+
+/** A range of chunk indices, {@code start} inclusive and {@code end} exclusive. */
+public final class ChunkRange {
+
+    public final long start;
+
+    public final long end;
+
+    public ChunkRange(long start, long end) {
+        this.start = start;
+        this.end = end;
+    }
+
+    @Override
+    public String toString() {
+        return String.format("%d..%d", this.start, this.end);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,17 @@
+package com.live2o3.chua;
+
+/** The requested chunk size is larger than the server accepts. */
+public class ChunkSizeException extends ServerException {
+
+    private final long maxChunkSize;
+
+    public ChunkSizeException(String message, long maxChunkSize) {
+        super(message);
+        this.maxChunkSize = maxChunkSize;
+    }
+
+    /** The largest chunk size in bytes accepted by the server, retry with it or pass 0. */
+    public long getMaxChunkSize() {
+        return this.maxChunkSize;
+    }
+}
\ No newline at end of file
@@ -0,0 +1,16 @@
+package com.live2o3.chua;
+
+public class ClientOptions {
+
+    /** Request timeout in milliseconds. Zero or negative values use the default of 20 seconds. */
+    public long timeoutMillis = 20000;
+
+    /** Http proxy, e.g. http://proxy.example.com:8080. Uses the system proxy when null. */
+    public String proxy;
+
+    /** Extra trusted root certificates in PEM format. */
+    public String[] rootCertificates;
+
+    /** Client certificate chain and private key in PEM format, for mutual TLS. */
+    public String identity;
+}
@@ -0,0 +1,9 @@
+package com.live2o3.chua;
+
+/** The local file could not be opened or read. */
+public class FileReadException extends ChuaException {
+
+    public FileReadException(String message) {
+        super(message);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,17 @@
+package com.live2o3.chua;
+
+/** The file is empty or larger than the server accepts. */
+public class FileSizeException extends ServerException {
+
+    private final long maxSize;
+
+    public FileSizeException(String message, long maxSize) {
+        super(message);
+        this.maxSize = maxSize;
+    }
+
+    /** The largest file size in bytes accepted by the server. */
+    public long getMaxSize() {
+        return this.maxSize;
+    }
+}
\ No newline at end of file
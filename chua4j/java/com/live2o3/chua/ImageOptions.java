@@ -0,0 +1,10 @@
+package com.live2o3.chua;
+
+public class ImageOptions {
+
+    /** Maximum width or height in pixels, larger images are downscaled. 0 keeps the original size. */
+    public int maxDimension = 0;
+
+    /** JPEG quality from 1 to 100. */
+    public int quality = 85;
+}
@@ -0,0 +1,21 @@
+package com.live2o3.chua;
+
+/** The server could not complete the upload because some chunks never arrived. */
+public class IncompleteUploadException extends ServerException {
+
+    private final ChunkRange[] missingChunks;
+
+    /** {@code missing} holds the start and end of each missing range in turn. */
+    public IncompleteUploadException(String message, long[] missing) {
+        super(message);
+        this.missingChunks = new ChunkRange[missing.length / 2];
+        for (int i = 0; i < this.missingChunks.length; i++) {
+            this.missingChunks[i] = new ChunkRange(missing[2 * i], missing[2 * i + 1]);
+        }
+    }
+
+    /** The chunks the server is missing. */
+    public ChunkRange[] getMissingChunks() {
+        return this.missingChunks.clone();
+    }
+}
\ No newline at end of file
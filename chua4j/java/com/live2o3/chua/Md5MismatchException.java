@@ -0,0 +1,25 @@
+package com.live2o3.chua;
+
+/** The md5 of the uploaded content does not match the one declared when the upload was initialized. */
+public class Md5MismatchException extends ServerException {
+
+    private final String expected;
+
+    private final String actual;
+
+    public Md5MismatchException(String message, String expected, String actual) {
+        super(message);
+        this.expected = expected;
+        this.actual = actual;
+    }
+
+    /** The md5 declared by the client. */
+    public String getExpected() {
+        return this.expected;
+    }
+
+    /** The md5 computed by the server. */
+    public String getActual() {
+        return this.actual;
+    }
+}
\ No newline at end of file
@@ -0,0 +1,9 @@
+package com.live2o3.chua;
+
+/** A request failed before the server answered, e.g. timeout, connection or TLS errors. */
+public class NetworkException extends ChuaException {
+
+    public NetworkException(String message) {
+        super(message);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,9 @@
+package com.live2o3.chua;
+
+/** The server rejected a request of the upload. Subclasses carry the details of known rejections. */
+public class ServerException extends ChuaException {
+
+    public ServerException(String message) {
+        super(message);
+    }
+}
\ No newline at end of file
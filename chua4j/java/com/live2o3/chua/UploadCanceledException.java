@@ -0,0 +1,9 @@
+package com.live2o3.chua;
+
+/** The upload was canceled by {@link UploadTask#cancel}. */
+public class UploadCanceledException extends ChuaException {
+
+    public UploadCanceledException(String message) {
+        super(message);
+    }
+}
\ No newline at end of file
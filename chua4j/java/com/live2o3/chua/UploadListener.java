@@ -0,0 +1,17 @@
+package com.live2o3.chua;
+
+/**
+ * Receives the progress and the outcome of an upload started by {@link Chua#uploadAsync}.
+ * Methods are called on native worker threads, switch to the main thread before touching UI.
+ */
+public interface UploadListener {
+
+    /** Called whenever a chunk is acknowledged by the server. */
+    void onProgress(long loaded, long total);
+
+    /** Called once when the upload succeeded, with the file id. */
+    void onSuccess(String id);
+
+    /** Called once when the upload failed or was canceled, with a {@link ChuaException} or one of its subclasses. */
+    void onFailure(Throwable cause);
+}
@@ -0,0 +1,31 @@
+package com.live2o3.chua;
+
+/** Handle of an upload started by {@link Chua#uploadAsync}. */
+public class UploadTask implements AutoCloseable {
+
+    private long handle;
+
+    UploadTask(long handle) {
+        this.handle = handle;
+    }
+
+    /** Cancels the upload, the server discards the uploaded chunks. The listener then receives {@code onFailure}. */
+    public synchronized void cancel() {
+        if (this.handle != 0) {
+            nativeCancel(this.handle);
+        }
+    }
+
+    /** Releases the native handle. The upload itself keeps running and the listener is still called. */
+    @Override
+    public synchronized void close() {
+        if (this.handle != 0) {
+            nativeFree(this.handle);
+            this.handle = 0;
+        }
+    }
+
+    private static native void nativeCancel(long handle);
+
+    private static native void nativeFree(long handle);
+}
@@ -0,0 +1,150 @@
+use chua::{ChuaError, CompleteError, InitializeError};
+use jni::objects::{GlobalRef, JClass, JObject, JThrowable, JValue};
+use jni::sys::jlong;
+use jni::JNIEnv;
+use lazy_static::lazy_static;
+use std::sync::Mutex;
+
+/// `JNI_OnLoad` 时缓存的异常类。Android 上 attach 的原生线程用系统的 ClassLoader，找不到应用的类
+struct Classes {
+    chua: GlobalRef,
+    file_read: GlobalRef,
+    network: GlobalRef,
+    canceled: GlobalRef,
+    server: GlobalRef,
+    file_size: GlobalRef,
+    chunk_size: GlobalRef,
+    incomplete: GlobalRef,
+    md5: GlobalRef,
+}
+
+lazy_static! {
+    static ref CLASSES: Mutex<Option<Classes>> = Mutex::new(None);
+}
+
+/// 在 `JNI_OnLoad` 中调用，此时能找到应用的类
+pub(crate) fn init(env: &JNIEnv) -> jni::errors::Result<()> {
+    let class = |name: &str| env.new_global_ref(env.find_class(name)?);
+
+    let classes = Classes {
+        chua: class("com/live2o3/chua/ChuaException")?,
+        file_read: class("com/live2o3/chua/FileReadException")?,
+        network: class("com/live2o3/chua/NetworkException")?,
+        canceled: class("com/live2o3/chua/UploadCanceledException")?,
+        server: class("com/live2o3/chua/ServerException")?,
+        file_size: class("com/live2o3/chua/FileSizeException")?,
+        chunk_size: class("com/live2o3/chua/ChunkSizeException")?,
+        incomplete: class("com/live2o3/chua/IncompleteUploadException")?,
+        md5: class("com/live2o3/chua/Md5MismatchException")?,
+    };
+
+    if let Ok(mut cached) = CLASSES.lock() {
+        *cached = Some(classes);
+    }
+
+    Ok(())
+}
+
+/// 把 `error` 转换成 `com.live2o3.chua.ChuaException` 或它的子类
+pub(crate) fn to_java<'a>(
+    env: &JNIEnv<'a>,
+    error: &ChuaError,
+) -> jni::errors::Result<JThrowable<'a>> {
+    let message = JObject::from(env.new_string(error.to_string())?);
+
+    // 没经过 `JNI_OnLoad` 时退回 `java.lang.Exception`
+    let cached = CLASSES.lock();
+    let classes = match cached.as_ref().ok().and_then(|classes| classes.as_ref()) {
+        Some(classes) => classes,
+        None => {
+            return Ok(env
+                .new_object(
+                    "java/lang/Exception",
+                    "(Ljava/lang/String;)V",
+                    &[JValue::Object(message)],
+                )?
+                .into())
+        }
+    };
+
+    let exception = match error {
+        ChuaError::Io(_) => new(env, &classes.file_read, "", &[JValue::Object(message)]),
+        ChuaError::Http(_) => new(env, &classes.network, "", &[JValue::Object(message)]),
+        ChuaError::Aborted => new(env, &classes.canceled, "", &[JValue::Object(message)]),
+        ChuaError::Initialize(InitializeError::Size { max }) => new(
+            env,
+            &classes.file_size,
+            "J",
+            &[JValue::Object(message), JValue::Long(*max as jlong)],
+        ),
+        ChuaError::Initialize(InitializeError::ChunkSize { max }) => new(
+            env,
+            &classes.chunk_size,
+            "J",
+            &[JValue::Object(message), JValue::Long(*max as jlong)],
+        ),
+        ChuaError::Complete(CompleteError::Incomplete { missing }) => {
+            let ranges: Vec<jlong> = missing
+                .iter()
+                .flat_map(|range| vec![range.start as jlong, range.end as jlong])
+                .collect();
+            let array = env.new_long_array(ranges.len() as i32)?;
+            env.set_long_array_region(array, 0, &ranges)?;
+            let array = JObject::from(array);
+
+            let exception = new(
+                env,
+                &classes.incomplete,
+                "[J",
+                &[JValue::Object(message), JValue::Object(array)],
+            );
+            let _ = env.delete_local_ref(array);
+            exception
+        }
+        ChuaError::Complete(CompleteError::MD5 { expected, actual }) => {
+            let expected = JObject::from(env.new_string(expected)?);
+            let actual = JObject::from(env.new_string(actual)?);
+
+            let exception = new(
+                env,
+                &classes.md5,
+                "Ljava/lang/String;Ljava/lang/String;",
+                &[
+                    JValue::Object(message),
+                    JValue::Object(expected),
+                    JValue::Object(actual),
+                ],
+            );
+            let _ = env.delete_local_ref(expected);
+            let _ = env.delete_local_ref(actual);
+            exception
+        }
+        ChuaError::Initialize(_)
+        | ChuaError::Append(_)
+        | ChuaError::UploadChunk(_)
+        | ChuaError::Complete(_)
+        | ChuaError::Status(_)
+        | ChuaError::Abort(_)
+        | ChuaError::HttpStatus { .. } => new(env, &classes.server, "", &[JValue::Object(message)]),
+        _ => new(env, &classes.chua, "", &[JValue::Object(message)]),
+    };
+
+    // 在原生线程中调用时局部引用不会自动释放
+    let _ = env.delete_local_ref(message);
+
+    exception
+}
+
+/// 调用构造函数 `(String message, <extra>)`
+fn new<'a>(
+    env: &JNIEnv<'a>,
+    class: &GlobalRef,
+    extra: &str,
+    args: &[JValue],
+) -> jni::errors::Result<JThrowable<'a>> {
+    let sig = format!("(Ljava/lang/String;{})V", extra);
+
+    Ok(env
+        .new_object(JClass::from(class.as_obj()), &sig, args)?
+        .into())
+}
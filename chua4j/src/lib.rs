@@ -1,28 +1,60 @@
 #![allow(non_snake_case)]
 
-use chua::upload;
-use jni::objects::{JClass, JObject, JString, JThrowable, JValue};
+mod error;
+
+use chua::{
+    blocking, upload_cancelable, upload_file, CancelToken, ChuaError, ChuaResult, ClientOptions,
+    FileInfo, ImageOptions, UploadEvent, UploadEvents,
+};
+use futures::{Future, FutureExt, StreamExt};
+use jni::objects::{GlobalRef, JClass, JObject, JString, JValue};
 use jni::sys::{jint, jlong, jsize, JNI_VERSION_1_6};
 use jni::{JNIEnv, JavaVM};
 use lazy_static::lazy_static;
-use std::fmt::Display;
+#[cfg(unix)]
+use std::fs::File;
 use std::os::raw::c_void;
-use tokio::runtime::Runtime;
+use std::panic::AssertUnwindSafe;
+use std::sync::Mutex;
+use std::time::Duration;
+use url::Url;
 use uuid::Uuid;
 
 lazy_static! {
-    static ref RUNTIME: Result<Runtime, String> = tokio::runtime::Builder::new()
-        .threaded_scheduler()
-        .enable_all()
-        .build()
-        .map_err(|e| e.to_string());
+    /// 加载时保存的 JavaVM，在运行时的线程中回调 Java 前用它 attach 线程
+    static ref JVM: Mutex<Option<JavaVM>> = Mutex::new(None);
+}
+
+/// 异步上传的句柄，指针以 `long` 交给 `com.live2o3.chua.UploadTask`
+struct UploadHandle {
+    cancel: CancelToken,
 }
 
 #[no_mangle]
-pub unsafe extern "system" fn JNI_OnLoad(_vm: *mut JavaVM, _reserved: *mut c_void) -> jint {
+pub unsafe extern "system" fn JNI_OnLoad(
+    vm: *mut jni::sys::JavaVM,
+    _reserved: *mut c_void,
+) -> jint {
     #[cfg(target_os = "android")]
     init_android_log();
 
+    if let Ok(vm) = JavaVM::from_raw(vm) {
+        match vm.get_env() {
+            Ok(env) => {
+                // 找不到类时留下的 NoClassDefFoundError 会让 loadLibrary 失败
+                if let Err(e) = error::init(&env) {
+                    log::warn!("Failed to load the exception classes: {}", e);
+                    let _ = env.exception_clear();
+                }
+            }
+            Err(e) => log::warn!("Failed to get the JNIEnv: {}", e),
+        }
+
+        if let Ok(mut jvm) = JVM.lock() {
+            *jvm = Some(vm);
+        }
+    }
+
     JNI_VERSION_1_6
 }
 
@@ -35,110 +67,573 @@ pub unsafe extern "system" fn Java_com_live2o3_chua_Chua_upload<'a>(
     chunk_size: jlong,
     parallel: jsize,
 ) -> JObject<'a> {
-    if base_url.is_null() {
-        return make_java_result(env, Err("Base url must not be null"));
+    upload(
+        env,
+        base_url,
+        path,
+        chunk_size,
+        parallel,
+        ClientOptions::default(),
+        None,
+    )
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_com_live2o3_chua_Chua_uploadWithOptions<'a>(
+    env: JNIEnv<'a>,
+    _class: JClass<'a>,
+    base_url: JString<'a>,
+    path: JString<'a>,
+    chunk_size: jlong,
+    parallel: jsize,
+    options: JObject<'a>,
+) -> JObject<'a> {
+    let options = if options.is_null() {
+        ClientOptions::default()
+    } else {
+        match get_client_options(&env, options) {
+            Ok(options) => options,
+            Err(e) => return make_java_result(env, Err(e.to_string().into())),
+        }
+    };
+
+    upload(env, base_url, path, chunk_size, parallel, options, None)
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_com_live2o3_chua_Chua_uploadImage<'a>(
+    env: JNIEnv<'a>,
+    _class: JClass<'a>,
+    base_url: JString<'a>,
+    path: JString<'a>,
+    chunk_size: jlong,
+    parallel: jsize,
+    image: JObject<'a>,
+    options: JObject<'a>,
+) -> JObject<'a> {
+    let image = if image.is_null() {
+        ImageOptions::default()
+    } else {
+        match get_image_options(&env, image) {
+            Ok(image) => image,
+            Err(e) => return make_java_result(env, Err(e.to_string().into())),
+        }
+    };
+
+    let options = if options.is_null() {
+        ClientOptions::default()
+    } else {
+        match get_client_options(&env, options) {
+            Ok(options) => options,
+            Err(e) => return make_java_result(env, Err(e.to_string().into())),
+        }
+    };
+
+    upload(
+        env,
+        base_url,
+        path,
+        chunk_size,
+        parallel,
+        options,
+        Some(image),
+    )
+}
+
+/// 在 `chua::blocking` 的运行时上开始上传并立即返回句柄，进度和结果通过 `listener` 回调。
+/// 参数不合法时抛出 `IllegalArgumentException` 并返回 0
+///
+/// # Safety
+///
+/// 由 JVM 调用
+#[no_mangle]
+pub unsafe extern "system" fn Java_com_live2o3_chua_Chua_startUpload<'a>(
+    env: JNIEnv<'a>,
+    _class: JClass<'a>,
+    base_url: JString<'a>,
+    path: JString<'a>,
+    chunk_size: jlong,
+    parallel: jsize,
+    options: JObject<'a>,
+    listener: JObject<'a>,
+) -> jlong {
+    match start_upload(
+        &env, base_url, path, chunk_size, parallel, options, listener,
+    ) {
+        Ok(handle) => handle,
+        Err(e) => {
+            let _ = env.throw_new("java/lang/IllegalArgumentException", e);
+            0
+        }
     }
+}
+
+/// 与 `startUpload` 相同，但上传已经打开的文件描述符 `fd`，如 `ParcelFileDescriptor.detachFd()`
+/// 的返回值。`fd` 的所有权转移给 chua4j，上传结束或参数不合法时关闭。`size` 为 -1 表示大小未知，
+/// 扩展名取自 `name`
+///
+/// # Safety
+///
+/// 由 JVM 调用，`fd` 必须是调用方拥有且不会再使用的文件描述符
+#[cfg(unix)]
+#[no_mangle]
+pub unsafe extern "system" fn Java_com_live2o3_chua_Chua_startFdUpload<'a>(
+    env: JNIEnv<'a>,
+    _class: JClass<'a>,
+    base_url: JString<'a>,
+    fd: jint,
+    size: jlong,
+    name: JString<'a>,
+    chunk_size: jlong,
+    parallel: jsize,
+    options: JObject<'a>,
+    listener: JObject<'a>,
+) -> jlong {
+    use std::os::unix::io::FromRawFd;
+
+    // 先取得所有权，之后任何一步失败都会关闭 fd
+    let result = if fd >= 0 {
+        let file = File::from_raw_fd(fd);
+        start_fd_upload(
+            &env, base_url, file, size, name, chunk_size, parallel, options, listener,
+        )
+    } else {
+        Err("File descriptor must not be negative".into())
+    };
 
-    let base_url: String = match env.get_string(base_url) {
-        Ok(base_url) => base_url,
-        Err(e) => return make_java_result(env.clone(), Err(e)),
+    match result {
+        Ok(handle) => handle,
+        Err(e) => {
+            let _ = env.throw_new("java/lang/IllegalArgumentException", e);
+            0
+        }
     }
-    .into();
+}
 
-    if path.is_null() {
-        return make_java_result(env, Err("Path must not be null"));
+/// 取消 `handle` 对应的上传
+///
+/// # Safety
+///
+/// `handle` 必须是 `startUpload` 返回且尚未释放的
+#[no_mangle]
+pub unsafe extern "system" fn Java_com_live2o3_chua_UploadTask_nativeCancel(
+    _env: JNIEnv,
+    _class: JClass,
+    handle: jlong,
+) {
+    if let Some(handle) = (handle as *const UploadHandle).as_ref() {
+        handle.cancel.cancel();
     }
+}
 
-    let path: String = match env.get_string(path) {
-        Ok(path) => path,
-        Err(e) => return make_java_result(env.clone(), Err(e)),
+/// 释放 `handle`，不会取消上传
+///
+/// # Safety
+///
+/// `handle` 必须是 `startUpload` 返回且尚未释放的
+#[no_mangle]
+pub unsafe extern "system" fn Java_com_live2o3_chua_UploadTask_nativeFree(
+    _env: JNIEnv,
+    _class: JClass,
+    handle: jlong,
+) {
+    if handle != 0 {
+        drop(Box::from_raw(handle as *mut UploadHandle));
     }
-    .into();
+}
+
+fn start_upload(
+    env: &JNIEnv,
+    base_url: JString,
+    path: JString,
+    chunk_size: jlong,
+    parallel: jsize,
+    options: JObject,
+    listener: JObject,
+) -> Result<jlong, String> {
+    if listener.is_null() {
+        return Err("Listener must not be null".into());
+    }
+
+    let (base_url, path, chunk_size, parallel) =
+        get_upload_args(env, base_url, path, chunk_size, parallel)?;
 
-    let chunk_size = if chunk_size > 0 {
-        chunk_size as u64
+    let base_url = Url::parse(&base_url).map_err(|e| e.to_string())?;
+
+    let options = if options.is_null() {
+        ClientOptions::default()
     } else {
-        return make_java_result(env, Err("Chunk size must be greater than 0"));
+        get_client_options(env, options).map_err(|e| e.to_string())?
     };
 
-    let parallel = if parallel >= 0 {
-        parallel as usize
+    let listener = env.new_global_ref(listener).map_err(|e| e.to_string())?;
+    let cancel = CancelToken::new();
+
+    let upload = upload_cancelable(
+        base_url,
+        path,
+        chunk_size,
+        parallel,
+        FileInfo::default(),
+        options,
+        cancel.clone(),
+    );
+
+    spawn_upload(upload, listener, cancel)
+}
+
+#[cfg(unix)]
+#[allow(clippy::too_many_arguments)]
+fn start_fd_upload(
+    env: &JNIEnv,
+    base_url: JString,
+    file: File,
+    size: jlong,
+    name: JString,
+    chunk_size: jlong,
+    parallel: jsize,
+    options: JObject,
+    listener: JObject,
+) -> Result<jlong, String> {
+    if listener.is_null() {
+        return Err("Listener must not be null".into());
+    }
+
+    let base_url = get_required_string(env, base_url, "Base url")?;
+    let base_url = Url::parse(&base_url).map_err(|e| e.to_string())?;
+    let name = get_required_string(env, name, "Name")?;
+    let (chunk_size, parallel) = get_chunk_args(chunk_size, parallel)?;
+
+    // 大小未知时为 -1，已知时与描述符的大小比较，避免传入的是管道等不能按位置读取的描述符
+    if size >= 0 {
+        let actual = file.metadata().map_err(|e| e.to_string())?.len();
+        if size as u64 != actual {
+            return Err(format!(
+                "The size ({}) does not match the file descriptor ({})",
+                size, actual
+            ));
+        }
+    }
+
+    let options = if options.is_null() {
+        ClientOptions::default()
     } else {
-        return make_java_result(env, Err("Parallel must not be less than 0"));
+        get_client_options(env, options).map_err(|e| e.to_string())?
     };
 
-    let result = RUNTIME
-        .as_ref()
-        .unwrap()
-        .handle()
-        .block_on(upload(&base_url, path, chunk_size, parallel));
+    let listener = env.new_global_ref(listener).map_err(|e| e.to_string())?;
+    let cancel = CancelToken::new();
+
+    let info = FileInfo {
+        name: Some(name),
+        ..FileInfo::default()
+    };
+
+    let upload = upload_file(
+        base_url,
+        file,
+        chunk_size,
+        parallel,
+        info,
+        options,
+        cancel.clone(),
+    );
+
+    spawn_upload(upload, listener, cancel)
+}
+
+/// 在 `chua::blocking` 的运行时上执行上传，把进度和结果回调给 `listener`，返回交给 Java 的句柄
+fn spawn_upload(
+    (future, events): (
+        impl Future<Output = ChuaResult<Uuid>> + Send + 'static,
+        UploadEvents,
+    ),
+    listener: GlobalRef,
+    cancel: CancelToken,
+) -> Result<jlong, String> {
+    let runtime = blocking::handle().map_err(|e| e.to_string())?;
+
+    runtime.spawn(async move {
+        // 上传中 panic 时也要回调 onFailure，否则 Java 一侧会一直等待
+        let result = AssertUnwindSafe(async {
+            let (result, _) = futures::join!(future, report_progress(events, &listener));
+            result
+        })
+        .catch_unwind()
+        .await
+        .unwrap_or_else(|panic| {
+            let message = match panic.downcast_ref::<&str>() {
+                Some(message) => message.to_string(),
+                None => match panic.downcast_ref::<String>() {
+                    Some(message) => message.clone(),
+                    None => "panicked".to_string(),
+                },
+            };
+
+            Err(ChuaError::Other(format!(
+                "The upload panicked: {}",
+                message
+            )))
+        });
+
+        report_result(&listener, result);
+    });
+
+    Ok(Box::into_raw(Box::new(UploadHandle { cancel })) as jlong)
+}
+
+/// 把上传事件转换成 `UploadListener.onProgress` 回调
+async fn report_progress(mut events: UploadEvents, listener: &GlobalRef) {
+    let mut loaded = 0;
+    let mut total = 0;
+
+    while let Some(event) = events.next().await {
+        match event {
+            UploadEvent::Initialized { size, .. } => total = size,
+            UploadEvent::ChunkAcknowledged { size, .. } => loaded += size,
+            _ => continue,
+        }
+
+        if let Some(env) = attach() {
+            call_listener(
+                &env,
+                listener,
+                "onProgress",
+                "(JJ)V",
+                &[JValue::Long(loaded as jlong), JValue::Long(total as jlong)],
+            );
+        }
+    }
+}
+
+/// 上传结束时回调 `UploadListener.onSuccess` 或 `onFailure`
+fn report_result(listener: &GlobalRef, result: ChuaResult<Uuid>) {
+    let env = match attach() {
+        Some(env) => env,
+        None => return,
+    };
+
+    let (name, sig, object) = match result {
+        Ok(id) => (
+            "onSuccess",
+            "(Ljava/lang/String;)V",
+            env.new_string(id.to_string()).map(JObject::from),
+        ),
+        Err(e) => (
+            "onFailure",
+            "(Ljava/lang/Throwable;)V",
+            error::to_java(&env, &e).map(JObject::from),
+        ),
+    };
+
+    match object {
+        Ok(object) => {
+            call_listener(&env, listener, name, sig, &[JValue::Object(object)]);
+
+            // 线程不会返回 Java，局部引用要自己释放
+            let _ = env.delete_local_ref(object);
+        }
+        Err(e) => log::warn!("Failed to report the upload result: {}", e),
+    }
+}
+
+/// 把运行时的线程以守护线程 attach 到 JVM，不会阻止 JVM 退出，线程退出时自动 detach
+fn attach() -> Option<JNIEnv<'static>> {
+    let jvm = JVM.lock().ok()?;
+    let vm = jvm.as_ref()?;
+
+    match vm.attach_current_thread_as_daemon() {
+        // 守护线程退出前一直保持 attach，JNIEnv 在这个线程中始终有效，不受锁的生命周期限制
+        Ok(env) => unsafe { JNIEnv::from_raw(env.get_native_interface()).ok() },
+        Err(e) => {
+            log::warn!("Failed to attach the thread to the JVM: {}", e);
+            None
+        }
+    }
+}
+
+/// 调用 listener 的方法，listener 抛出的异常打印后清除，不影响上传
+fn call_listener(env: &JNIEnv, listener: &GlobalRef, name: &str, sig: &str, args: &[JValue]) {
+    if let Err(e) = env.call_method(listener.as_obj(), name, sig, args) {
+        log::warn!("Failed to call UploadListener.{}: {}", name, e);
+
+        if env.exception_check().unwrap_or(false) {
+            let _ = env.exception_describe();
+            let _ = env.exception_clear();
+        }
+    }
+}
+
+fn upload<'a>(
+    env: JNIEnv<'a>,
+    base_url: JString<'a>,
+    path: JString<'a>,
+    chunk_size: jlong,
+    parallel: jsize,
+    options: ClientOptions,
+    image: Option<ImageOptions>,
+) -> JObject<'a> {
+    let (base_url, path, chunk_size, parallel) =
+        match get_upload_args(&env, base_url, path, chunk_size, parallel) {
+            Ok(args) => args,
+            Err(e) => return make_java_result(env, Err(e.into())),
+        };
+
+    let result = match image {
+        Some(image) => blocking::upload_image(
+            &base_url,
+            path,
+            chunk_size,
+            parallel,
+            &image,
+            FileInfo::default(),
+            &options,
+        ),
+        None => blocking::upload_with_options(&base_url, path, chunk_size, parallel, &options),
+    };
 
     make_java_result(env, result)
 }
 
-fn make_java_result<E: Display>(env: JNIEnv, result: Result<Uuid, E>) -> JObject {
+/// 检查并转换上传的参数
+fn get_upload_args(
+    env: &JNIEnv,
+    base_url: JString,
+    path: JString,
+    chunk_size: jlong,
+    parallel: jsize,
+) -> Result<(String, String, u64, usize), String> {
+    let base_url = get_required_string(env, base_url, "Base url")?;
+    let path = get_required_string(env, path, "Path")?;
+    let (chunk_size, parallel) = get_chunk_args(chunk_size, parallel)?;
+
+    Ok((base_url, path, chunk_size, parallel))
+}
+
+fn get_required_string(env: &JNIEnv, s: JString, name: &str) -> Result<String, String> {
+    if s.is_null() {
+        return Err(format!("{} must not be null", name));
+    }
+
+    Ok(env.get_string(s).map_err(|e| e.to_string())?.into())
+}
+
+fn get_chunk_args(chunk_size: jlong, parallel: jsize) -> Result<(u64, usize), String> {
+    // 为 0 时由服务端的限制决定分片大小
+    if chunk_size < 0 {
+        return Err("Chunk size must not be less than 0".into());
+    }
+
+    if parallel < 0 {
+        return Err("Parallel must not be less than 0".into());
+    }
+
+    Ok((chunk_size as u64, parallel as usize))
+}
+
+/// 读取 `com.live2o3.chua.ClientOptions` 对象的字段
+fn get_client_options(env: &JNIEnv, options: JObject) -> jni::errors::Result<ClientOptions> {
+    let timeout = env.get_field(options, "timeoutMillis", "J")?.j()?;
+
+    let proxy = get_optional_string(
+        env,
+        env.get_field(options, "proxy", "Ljava/lang/String;")?.l()?,
+    )?;
+
+    let identity = get_optional_string(
+        env,
+        env.get_field(options, "identity", "Ljava/lang/String;")?
+            .l()?,
+    )?
+    .map(String::into_bytes);
+
+    let certificates = env
+        .get_field(options, "rootCertificates", "[Ljava/lang/String;")?
+        .l()?;
+
+    let mut root_certificates = Vec::new();
+    if !certificates.is_null() {
+        let certificates = certificates.into_inner();
+        for i in 0..env.get_array_length(certificates)? {
+            let certificate = env.get_object_array_element(certificates, i)?;
+            if let Some(pem) = get_optional_string(env, certificate)? {
+                root_certificates.push(pem.into_bytes());
+            }
+        }
+    }
+
+    let mut client_options = ClientOptions {
+        proxy,
+        root_certificates,
+        identity,
+        ..ClientOptions::default()
+    };
+
+    // 与 chua4c 一致，0 和负数使用默认的超时
+    if timeout > 0 {
+        client_options.timeout = Duration::from_millis(timeout as u64);
+    }
+
+    Ok(client_options)
+}
+
+/// 读取 `com.live2o3.chua.ImageOptions` 对象的字段
+fn get_image_options(env: &JNIEnv, image: JObject) -> jni::errors::Result<ImageOptions> {
+    let max_dimension = env.get_field(image, "maxDimension", "I")?.i()?;
+    let quality = env.get_field(image, "quality", "I")?.i()?;
+
+    Ok(ImageOptions {
+        max_dimension: if max_dimension > 0 {
+            Some(max_dimension as u32)
+        } else {
+            None
+        },
+        quality: quality.clamp(1, 100) as u8,
+    })
+}
+
+fn get_optional_string(env: &JNIEnv, s: JObject) -> jni::errors::Result<Option<String>> {
+    if s.is_null() {
+        Ok(None)
+    } else {
+        Ok(Some(env.get_string(JString::from(s))?.into()))
+    }
+}
+
+/// 把结果包装成 `com.live2o3.Result`，失败时的 cause 是 `com.live2o3.chua.ChuaException` 或它的子类
+fn make_java_result(env: JNIEnv, result: ChuaResult<Uuid>) -> JObject {
     let class = env
         .find_class("com/live2o3/Result")
         .expect("Cannot find class 'Result'");
 
     let java_result = match result {
-        Ok(uuid) => match env.new_string(uuid.to_string()) {
-            Ok(uuid) => env
-                .call_static_method(
-                    class,
-                    "succeed",
-                    "(Ljava/lang/Object;)Lcom/live2o3/Result;",
-                    &[JValue::Object(JObject::from(uuid))],
-                )
-                .expect("Failed to call static method 'com.live2o3.Result.succeed'"),
-            Err(e) => {
-                let error = env
-                    .new_string(e.to_string())
-                    .expect("Failed to create a string");
-                let cause: JThrowable = env
-                    .new_object(
-                        "java/lang/Exception",
-                        "(Ljava/lang/String;)V",
-                        &[JValue::from(error)],
-                    )
-                    .expect("Failed to create a Exception object")
-                    .into();
-                env.call_static_method(
-                    class,
-                    "fail",
-                    "(Ljava/lang/Throwable;)Lcom/live2o3/Result;",
-                    &[JValue::Object(cause.into())],
-                )
-                .expect("Failed to call static method 'com.live2o3.Result.succeed'")
-            }
-        },
-        Err(e) => {
-            let error = env
-                .new_string(e.to_string())
+        Ok(uuid) => {
+            let uuid = env
+                .new_string(uuid.to_string())
                 .expect("Failed to create a string");
-            let cause: JThrowable = env
-                .new_object(
-                    "java/lang/Exception",
-                    "(Ljava/lang/String;)V",
-                    &[JValue::from(error)],
-                )
-                .expect("Failed to create a Exception object")
-                .into();
+            env.call_static_method(
+                class,
+                "succeed",
+                "(Ljava/lang/Object;)Lcom/live2o3/Result;",
+                &[JValue::Object(JObject::from(uuid))],
+            )
+            .expect("Failed to call static method 'com.live2o3.Result.succeed'")
+        }
+        Err(e) => {
+            let cause = error::to_java(&env, &e).expect("Failed to create a ChuaException object");
             env.call_static_method(
                 class,
                 "fail",
                 "(Ljava/lang/Throwable;)Lcom/live2o3/Result;",
                 &[JValue::Object(cause.into())],
             )
-            .expect("Failed to call static method 'com.live2o3.Result.succeed'")
+            .expect("Failed to call static method 'com.live2o3.Result.fail'")
         }
     };
 
-    JObject::from(
-        java_result
-            .l()
-            .expect("Failed to unwrap 'JValue' to a Java Object."),
-    )
+    java_result
+        .l()
+        .expect("Failed to unwrap 'JValue' to a Java Object.")
 }
 
 #[cfg(target_os = "android")]
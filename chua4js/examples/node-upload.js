@@ -0,0 +1,29 @@
+// 在 Node.js 中上传本地文件，可以用来对本地的 chua-server 做测试：
+//
+//   cargo run -p chua-server -- -p 8080 -c 4194304 -f 1073741824 -s /tmp/chua/static -t /tmp/chua/temp
+//   wasm-pack build --release --target nodejs --out-dir pkg-node -- --features node
+//   node examples/node-upload.js http://127.0.0.1:8080/ ./some-file
+//
+// 需要 Node.js 18 以上（全局的 fetch、FormData 和 Blob）。
+const { uploadFile } = require("../pkg-node");
+
+async function main() {
+    const [baseUrl, path] = process.argv.slice(2);
+    if (!baseUrl || !path) {
+        console.error("usage: node examples/node-upload.js <base-url> <file>");
+        process.exit(2);
+    }
+
+    const result = await uploadFile(baseUrl, path, 0, 0, {
+        hash: true,
+        onHashProgress: (loaded, total) => console.log(`hashing ${loaded}/${total} bytes`),
+        onProgress: (p) => console.log(`${p.loaded}/${p.total} bytes, ${p.chunks}/${p.totalChunks} chunks`),
+    });
+
+    console.log(`File ${path} uploaded.(id: ${result.id}, duplicated: ${result.duplicated})`);
+}
+
+main().catch((e) => {
+    console.error(e);
+    process.exit(1);
+});
@@ -0,0 +1,86 @@
+use chua::ChuaError;
+use serde::Serialize;
+use wasm_bindgen::prelude::*;
+
+#[wasm_bindgen(typescript_custom_section)]
+const ERROR_TYPES: &str = r#"
+/** 分片序号区间，不含 `end` */
+export interface ChunkRange {
+    start: number;
+    end: number;
+}
+
+/** 初始化失败的原因 */
+export type InitializeError =
+    | { type: "Size"; max: number }
+    | { type: "ChunkSize"; max: number }
+    | { type: "Other"; detail: string };
+
+/** 上传分片失败的原因 */
+export type UploadChunkError =
+    | { type: "Size"; expected: number; actual: number }
+    | { type: "Other"; detail: string };
+
+/** 完成上传失败的原因 */
+export type CompleteError =
+    | { type: "Incomplete"; missing: ChunkRange[] }
+    | { type: "MD5"; expected: string; actual: string }
+    | { type: "Other"; detail: string };
+
+/** 上传失败时 Promise 的错误，按 `kind` 区分，服务端返回的错误在 `detail` 中 */
+export type ChuaError =
+    | (Error & { name: "ChuaError"; kind: "initialize"; detail: InitializeError })
+    | (Error & { name: "ChuaError"; kind: "uploadChunk"; detail: UploadChunkError })
+    | (Error & { name: "ChuaError"; kind: "complete"; detail: CompleteError })
+    | (Error & { name: "AbortError"; kind: "aborted" })
+    | (Error & { name: "ChuaError"; kind: "other" });
+
+/** 是否是上传时产生的 `ChuaError` */
+export function isChuaError(error: unknown): error is ChuaError;
+"#;
+
+/// 把上传的错误转换成 JS 的 `Error`，带上 `kind` 和服务端返回的 `detail`。
+/// 与 fetch 一致，取消时的错误名为 `AbortError`
+pub fn to_js(error: ChuaError) -> JsValue {
+    let (kind, detail) = match &error {
+        ChuaError::Initialize(e) => ("initialize", detail(e)),
+        ChuaError::UploadChunk(e) => ("uploadChunk", detail(e)),
+        ChuaError::Complete(e) => ("complete", detail(e)),
+        ChuaError::Aborted => ("aborted", None),
+        _ => ("other", None),
+    };
+
+    let js_error = match error {
+        ChuaError::Aborted => js_sys::Error::new("The upload was aborted"),
+        error => js_sys::Error::new(&error.to_string()),
+    };
+
+    js_error.set_name(if kind == "aborted" {
+        "AbortError"
+    } else {
+        "ChuaError"
+    });
+
+    let _ = js_sys::Reflect::set(&js_error, &"kind".into(), &kind.into());
+    if let Some(detail) = detail {
+        let _ = js_sys::Reflect::set(&js_error, &"detail".into(), &detail);
+    }
+
+    js_error.into()
+}
+
+/// 是否是上传时产生的 `ChuaError`
+#[wasm_bindgen(js_name = isChuaError, skip_typescript)]
+pub fn is_chua_error(error: &JsValue) -> bool {
+    error.is_instance_of::<js_sys::Error>()
+        && js_sys::Reflect::get(error, &"kind".into())
+            .map(|kind| kind.is_string())
+            .unwrap_or(false)
+}
+
+/// 协议中的错误按服务端返回的 JSON 原样交给 JS
+fn detail(error: &impl Serialize) -> Option<JsValue> {
+    let json = serde_json::to_string(error).ok()?;
+
+    js_sys::JSON::parse(&json).ok()
+}
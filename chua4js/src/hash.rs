@@ -0,0 +1,133 @@
+use futures::channel::mpsc;
+use futures::StreamExt;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{Blob, BlobPropertyBag, ErrorEvent, MessageEvent, Url, Worker};
+
+/// Worker 的脚本，增量计算 MD5
+const WORKER_SOURCE: &str = include_str!("hash_worker.js");
+
+/// 每次读取的分片大小
+const BLOCK_SIZE: f64 = 4.0 * 1024.0 * 1024.0;
+
+/// 在专用的 Worker 中计算 `blob` 的 md5，不阻塞页面。没有 Worker 的环境（如 Node.js）
+/// 中直接在当前线程计算。每处理完一个分片以 `(loaded, total)` 调用 `on_progress`
+pub async fn md5(blob: &Blob, on_progress: Option<&js_sys::Function>) -> Result<String, JsValue> {
+    let has_worker = js_sys::Reflect::get(&js_sys::global(), &"Worker".into())
+        .map(|worker| worker.is_function())
+        .unwrap_or(false);
+
+    if has_worker {
+        in_worker(blob, on_progress).await
+    } else {
+        in_place(blob, on_progress).await
+    }
+}
+
+async fn in_worker(blob: &Blob, on_progress: Option<&js_sys::Function>) -> Result<String, JsValue> {
+    let parts = js_sys::Array::of1(&JsValue::from_str(WORKER_SOURCE));
+    let mut options = BlobPropertyBag::new();
+    options.type_("application/javascript");
+
+    let script = Blob::new_with_str_sequence_and_options(&parts, &options)?;
+    let url = Url::create_object_url_with_blob(&script)?;
+
+    let worker = match Worker::new(&url) {
+        Ok(worker) => worker,
+        Err(e) => {
+            let _ = Url::revoke_object_url(&url);
+            return Err(e);
+        }
+    };
+
+    let (sender, mut receiver) = mpsc::unbounded();
+
+    let on_message = {
+        let sender = sender.clone();
+        Closure::wrap(Box::new(move |event: MessageEvent| {
+            let _ = sender.unbounded_send(Ok(event.data()));
+        }) as Box<dyn FnMut(MessageEvent)>)
+    };
+
+    // 脚本加载失败等错误不会以消息的形式返回
+    let on_error = Closure::wrap(Box::new(move |event: ErrorEvent| {
+        let _ = sender.unbounded_send(Err(js_sys::Error::new(&event.message()).into()));
+    }) as Box<dyn FnMut(ErrorEvent)>);
+
+    worker.set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+    worker.set_onerror(Some(on_error.as_ref().unchecked_ref()));
+
+    let result = async {
+        let total = blob.size();
+        let mut start = 0.0;
+
+        // 收到上一个分片的回复后再发送下一个，避免把整个文件的分片都堆在 Worker 的队列里
+        while start < total {
+            let end = (start + BLOCK_SIZE).min(total);
+
+            worker.post_message(&blob.slice_with_f64_and_f64(start, end)?)?;
+            reply(&mut receiver).await?;
+
+            if let Some(callback) = on_progress {
+                let _ = callback.call2(&JsValue::NULL, &end.into(), &total.into());
+            }
+
+            start = end;
+        }
+
+        worker.post_message(&JsValue::NULL)?;
+
+        js_sys::Reflect::get(&reply(&mut receiver).await?, &"md5".into())?
+            .as_string()
+            .ok_or_else(|| JsValue::from(js_sys::Error::new("The hash worker returned no md5")))
+    }
+    .await;
+
+    worker.set_onmessage(None);
+    worker.set_onerror(None);
+    worker.terminate();
+    let _ = Url::revoke_object_url(&url);
+
+    result
+}
+
+/// 逐个读取分片并计算 md5，读取分片时会让出线程
+async fn in_place(blob: &Blob, on_progress: Option<&js_sys::Function>) -> Result<String, JsValue> {
+    let mut context = md5::Context::new();
+    let total = blob.size();
+    let mut start = 0.0;
+
+    while start < total {
+        let end = (start + BLOCK_SIZE).min(total);
+
+        let slice = blob.slice_with_f64_and_f64(start, end)?;
+        let buffer = JsFuture::from(slice.array_buffer()).await?;
+        context.consume(js_sys::Uint8Array::new(&buffer).to_vec());
+
+        if let Some(callback) = on_progress {
+            let _ = callback.call2(&JsValue::NULL, &end.into(), &total.into());
+        }
+
+        start = end;
+    }
+
+    Ok(format!("{:x}", context.compute()))
+}
+
+/// 等待 Worker 的回复，回复中带有 `error` 时返回错误
+async fn reply(
+    receiver: &mut mpsc::UnboundedReceiver<Result<JsValue, JsValue>>,
+) -> Result<JsValue, JsValue> {
+    let data = match receiver.next().await {
+        Some(data) => data?,
+        None => return Err(js_sys::Error::new("The hash worker exited unexpectedly").into()),
+    };
+
+    let error = js_sys::Reflect::get(&data, &"error".into())?;
+
+    match error.as_string() {
+        Some(message) => Err(js_sys::Error::new(&message).into()),
+        None => Ok(data),
+    }
+}
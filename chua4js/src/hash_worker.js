@@ -0,0 +1,110 @@
+// 在 Worker 中增量计算 MD5。
+// 主线程逐个发送 Blob 分片，每处理完一个回复 { loaded }；发送 null 时回复 { md5 }，出错时回复 { error }。
+"use strict";
+
+const S = [7, 12, 17, 22, 5, 9, 14, 20, 4, 11, 16, 23, 6, 10, 15, 21];
+const K = new Int32Array(64);
+for (let i = 0; i < 64; i++) {
+    K[i] = Math.floor(Math.abs(Math.sin(i + 1)) * 4294967296) | 0;
+}
+
+const state = new Int32Array([0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476]);
+const words = new Int32Array(16);
+const buffer = new Uint8Array(64);
+let buffered = 0;
+let length = 0;
+
+function block(bytes, offset) {
+    for (let i = 0; i < 16; i++) {
+        const j = offset + i * 4;
+        words[i] = bytes[j] | (bytes[j + 1] << 8) | (bytes[j + 2] << 16) | (bytes[j + 3] << 24);
+    }
+
+    let a = state[0], b = state[1], c = state[2], d = state[3];
+
+    for (let i = 0; i < 64; i++) {
+        let f, g;
+        if (i < 16) {
+            f = (b & c) | (~b & d);
+            g = i;
+        } else if (i < 32) {
+            f = (d & b) | (~d & c);
+            g = (5 * i + 1) & 15;
+        } else if (i < 48) {
+            f = b ^ c ^ d;
+            g = (3 * i + 5) & 15;
+        } else {
+            f = c ^ (b | ~d);
+            g = (7 * i) & 15;
+        }
+
+        const x = (a + f + K[i] + words[g]) | 0;
+        const s = S[(i >> 4) * 4 + (i & 3)];
+        a = d;
+        d = c;
+        c = b;
+        b = (b + ((x << s) | (x >>> (32 - s)))) | 0;
+    }
+
+    state[0] += a;
+    state[1] += b;
+    state[2] += c;
+    state[3] += d;
+}
+
+function update(bytes) {
+    length += bytes.length;
+
+    let i = 0;
+    if (buffered > 0) {
+        i = Math.min(64 - buffered, bytes.length);
+        buffer.set(bytes.subarray(0, i), buffered);
+        buffered += i;
+        if (buffered < 64) {
+            return;
+        }
+        block(buffer, 0);
+        buffered = 0;
+    }
+
+    for (; i + 64 <= bytes.length; i += 64) {
+        block(bytes, i);
+    }
+
+    buffer.set(bytes.subarray(i), 0);
+    buffered = bytes.length - i;
+}
+
+function finish() {
+    const bits = length * 8;
+    const tail = new Uint8Array(buffered < 56 ? 64 : 128);
+    tail.set(buffer.subarray(0, buffered));
+    tail[buffered] = 0x80;
+
+    const view = new DataView(tail.buffer);
+    view.setUint32(tail.length - 8, bits >>> 0, true);
+    view.setUint32(tail.length - 4, Math.floor(bits / 4294967296), true);
+
+    for (let i = 0; i < tail.length; i += 64) {
+        block(tail, i);
+    }
+
+    let hex = "";
+    for (const byte of new Uint8Array(state.buffer)) {
+        hex += byte.toString(16).padStart(2, "0");
+    }
+    return hex;
+}
+
+self.onmessage = (event) => {
+    try {
+        if (event.data === null) {
+            self.postMessage({ md5: finish() });
+        } else {
+            update(new Uint8Array(new FileReaderSync().readAsArrayBuffer(event.data)));
+            self.postMessage({ loaded: length });
+        }
+    } catch (e) {
+        self.postMessage({ error: String(e) });
+    }
+};
@@ -1,14 +1,438 @@
+mod error;
+mod hash;
+#[cfg(feature = "node")]
+mod node;
+mod resume;
+mod source;
+
+use chua::{
+    CancelToken, Capability, ChuaError, ChuaResult, ClientOptions, FileInfo, StatusError,
+    UploadEvent, UploadEvents,
+};
+use futures::{Future, StreamExt};
+use resume::Session;
+use std::time::Duration;
+use uuid::Uuid;
 use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use web_sys::AbortSignal;
+
+#[wasm_bindgen(typescript_custom_section)]
+const UPLOAD_TYPES: &str = r#"
+/** 可以上传的数据源 */
+export type UploadSource = Blob | ArrayBuffer | ArrayBufferView | ReadableStream;
+
+/** `upload` 的可选参数 */
+export interface UploadOptions {
+    /** 进度回调 */
+    onProgress?: (progress: Progress) => void;
+    /** 用于取消上传，取消时 Promise 以 `AbortError` 失败 */
+    signal?: AbortSignal;
+    /** 单个请求的超时时间（毫秒） */
+    timeout?: number;
+    /** 跨域请求时是否携带 Cookie 等凭据 */
+    credentials?: boolean;
+    /** 是否在 localStorage 中记录上传会话，刷新页面后再次选择同一个文件时接续上传 */
+    resume?: boolean;
+    /** 是否先在 Worker 中计算文件的 md5 并提交给服务端 */
+    hash?: boolean;
+    /** 计算 md5 的进度回调 */
+    onHashProgress?: (loaded: number, total: number) => void;
+    /** 提交给服务端的文件名，扩展名也从中取得 */
+    name?: string;
+    /** MIME 类型 */
+    contentType?: string;
+}
+
+/** 上传文件，失败时 Promise 以 `ChuaError` 失败 */
+export function upload(
+    baseUrl: string,
+    source: UploadSource,
+    chunkSize: number,
+    parallel: number,
+    options?: UploadOptions,
+): Promise<UploadResult>;
+
+/** 在 Worker 中计算 `blob` 的 md5 */
+export function md5(blob: Blob, onProgress?: (loaded: number, total: number) => void): Promise<string>;
+"#;
+
+#[cfg(feature = "node")]
+#[wasm_bindgen(typescript_custom_section)]
+const NODE_TYPES: &str = r#"
+/** 上传本地文件 `path`，只在 Node.js 中可用 */
+export function uploadFile(
+    baseUrl: string,
+    path: string,
+    chunkSize: number,
+    parallel: number,
+    options?: UploadOptions,
+): Promise<UploadResult>;
+"#;
+
+#[wasm_bindgen]
+extern "C" {
+    /// `upload` 的可选参数
+    pub type UploadOptions;
+
+    /// 进度回调，参数为 `Progress`
+    #[wasm_bindgen(method, getter, js_name = onProgress)]
+    fn on_progress(this: &UploadOptions) -> Option<js_sys::Function>;
+
+    /// 用于取消上传的 `AbortSignal`
+    #[wasm_bindgen(method, getter)]
+    fn signal(this: &UploadOptions) -> Option<AbortSignal>;
+
+    /// 单个请求的超时时间（毫秒）
+    #[wasm_bindgen(method, getter)]
+    fn timeout(this: &UploadOptions) -> Option<f64>;
+
+    /// 跨域请求时是否携带 Cookie 等凭据
+    #[wasm_bindgen(method, getter)]
+    fn credentials(this: &UploadOptions) -> Option<bool>;
+
+    /// 是否在 localStorage 中记录上传会话，刷新页面后再次选择同一个文件时接续上传
+    #[wasm_bindgen(method, getter)]
+    fn resume(this: &UploadOptions) -> Option<bool>;
+
+    /// 是否先在 Worker 中计算文件的 md5 并提交给服务端
+    #[wasm_bindgen(method, getter)]
+    fn hash(this: &UploadOptions) -> Option<bool>;
+
+    /// 计算 md5 的进度回调，参数为 `(loaded, total)`
+    #[wasm_bindgen(method, getter, js_name = onHashProgress)]
+    fn on_hash_progress(this: &UploadOptions) -> Option<js_sys::Function>;
+
+    /// 提交给服务端的文件名，扩展名也从中取得。`File` 默认使用它自己的文件名
+    #[wasm_bindgen(method, getter)]
+    fn name(this: &UploadOptions) -> Option<String>;
+
+    /// MIME 类型，默认使用 `Blob.type`，为空时按扩展名推断
+    #[wasm_bindgen(method, getter, js_name = contentType)]
+    fn content_type(this: &UploadOptions) -> Option<String>;
+}
+
+/// 上传进度
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy)]
+pub struct Progress {
+    /// 已上传的字节数
+    pub loaded: f64,
+
+    /// 总字节数
+    pub total: f64,
 
+    /// 已上传的分片数
+    pub chunks: u32,
+
+    /// 分片总数
+    #[wasm_bindgen(js_name = totalChunks)]
+    pub total_chunks: u32,
+
+    /// 平均速度（字节/秒）
+    pub throughput: f64,
+}
+
+/// 上传结果
 #[wasm_bindgen]
+#[derive(Debug, Clone)]
+pub struct UploadResult {
+    id: String,
+    duplicated: bool,
+    size: f64,
+    elapsed: f64,
+}
+
+#[wasm_bindgen]
+impl UploadResult {
+    /// 文件ID
+    #[wasm_bindgen(getter)]
+    pub fn id(&self) -> String {
+        self.id.clone()
+    }
+
+    /// 服务端是否已有这个文件
+    #[wasm_bindgen(getter)]
+    pub fn duplicated(&self) -> bool {
+        self.duplicated
+    }
+
+    /// 文件大小（字节）
+    #[wasm_bindgen(getter)]
+    pub fn size(&self) -> f64 {
+        self.size
+    }
+
+    /// 耗时（毫秒）
+    #[wasm_bindgen(getter)]
+    pub fn elapsed(&self) -> f64 {
+        self.elapsed
+    }
+}
+
+// 异步函数生成的声明返回 `Promise<any>`，这几个函数的声明写在上面
+#[wasm_bindgen(skip_typescript)]
 pub async fn upload(
     base_url: String,
-    file: web_sys::File,
+    source: JsValue,
+    chunk_size: f64,
+    parallel: usize,
+    options: Option<UploadOptions>,
+) -> Result<UploadResult, JsValue> {
+    let file = source::to_blob(source).await?;
+
+    upload_blob(base_url, file, None, chunk_size, parallel, options).await
+}
+
+/// 上传本地文件 `path`，只在 Node.js 中可用。文件名默认取自路径
+#[cfg(feature = "node")]
+#[wasm_bindgen(js_name = uploadFile, skip_typescript)]
+pub async fn upload_file(
+    base_url: String,
+    path: String,
     chunk_size: f64,
     parallel: usize,
-) -> Result<JsValue, JsValue> {
-    match chua::upload(&base_url, file, chunk_size as u64, parallel).await {
-        Ok(uuid) => Ok(JsValue::from_str(&uuid.to_string())),
-        Err(e) => Err(JsValue::from_str(&e.to_string())),
+    options: Option<UploadOptions>,
+) -> Result<UploadResult, JsValue> {
+    let file = node::open(&path).await?;
+    let name = node::file_name(&path).to_string();
+
+    upload_blob(base_url, file, Some(name), chunk_size, parallel, options).await
+}
+
+/// `options` 中没有指定文件名时使用 `default_name`
+async fn upload_blob(
+    base_url: String,
+    file: web_sys::Blob,
+    default_name: Option<String>,
+    chunk_size: f64,
+    parallel: usize,
+    options: Option<UploadOptions>,
+) -> Result<UploadResult, JsValue> {
+    let start = js_sys::Date::now();
+    let size = file.size();
+
+    let mut client_options = ClientOptions::default();
+    let mut on_progress = None;
+    let mut signal = None;
+    let mut resumable = false;
+    let mut compute_md5 = false;
+    let mut on_hash_progress = None;
+    let mut name = None;
+    let mut content_type = None;
+
+    if let Some(options) = &options {
+        if let Some(timeout) = options.timeout() {
+            client_options.timeout = Duration::from_millis(timeout as u64);
+        }
+
+        if let Some(credentials) = options.credentials() {
+            client_options.credentials = credentials;
+        }
+
+        on_progress = options.on_progress();
+        signal = options.signal();
+        resumable = options.resume().unwrap_or(false);
+        compute_md5 = options.hash().unwrap_or(false);
+        on_hash_progress = options.on_hash_progress();
+        name = options.name();
+        content_type = options.content_type();
+    }
+
+    let name = name.or(default_name);
+
+    let session = if resumable {
+        let (file_name, last_modified) = match file.dyn_ref::<web_sys::File>() {
+            Some(file) => (file.name(), file.last_modified()),
+            None => (String::new(), 0.0),
+        };
+
+        let name = name.clone().unwrap_or(file_name);
+
+        Some(Session::new(&base_url, &file, name, last_modified).await?)
+    } else {
+        None
+    };
+
+    let cancel = CancelToken::new();
+
+    // AbortSignal 触发时取消上传
+    let on_abort = {
+        let cancel = cancel.clone();
+        Closure::wrap(Box::new(move || cancel.cancel()) as Box<dyn FnMut()>)
+    };
+
+    if let Some(signal) = &signal {
+        if signal.aborted() {
+            cancel.cancel();
+        }
+
+        signal.add_event_listener_with_callback("abort", on_abort.as_ref().unchecked_ref())?;
+    }
+
+    let record = session.as_ref().and_then(Session::load);
+    let mut outcome = None;
+
+    if let Some(id) = record.and_then(|record| Uuid::parse_str(&record.id).ok()) {
+        let (future, events) = chua::resume_cancelable(
+            &base_url,
+            id,
+            file.clone(),
+            parallel,
+            client_options.clone(),
+            cancel.clone(),
+        );
+
+        match track(future, events, on_progress.clone(), start, None).await {
+            // 服务端已经没有这次上传、不能查询进度，或者文件有变化，重新上传。
+            // 其它错误（如网络中断）直接返回，保留记录以便下次接续
+            (Err(ChuaError::Status(StatusError::NotFound)), _)
+            | (Err(ChuaError::Unsupported(Capability::Status)), _)
+            | (Err(ChuaError::SizeMismatch { .. }), _) => {}
+            result => outcome = Some(result),
+        }
+    }
+
+    let outcome = match outcome {
+        Some(outcome) => Ok(outcome),
+        None => {
+            let md5 = if compute_md5 {
+                hash::md5(&file, on_hash_progress.as_ref()).await.map(Some)
+            } else {
+                Ok(None)
+            };
+
+            match md5 {
+                Err(e) => Err(e),
+                // 计算 md5 期间被取消
+                Ok(_) if cancel.is_canceled() => Ok((Err(ChuaError::Aborted), false)),
+                Ok(md5) => {
+                    let info = FileInfo {
+                        name,
+                        content_type,
+                        md5,
+                        ..FileInfo::default()
+                    };
+
+                    let (future, events) = chua::upload_cancelable(
+                        &base_url,
+                        file,
+                        chunk_size as u64,
+                        parallel,
+                        info,
+                        client_options,
+                        cancel,
+                    );
+
+                    Ok(track(future, events, on_progress, start, session.clone()).await)
+                }
+            }
+        }
+    };
+
+    if let Some(signal) = &signal {
+        let _ =
+            signal.remove_event_listener_with_callback("abort", on_abort.as_ref().unchecked_ref());
+    }
+
+    let (result, duplicated) = outcome?;
+
+    // 失败时保留记录，下次选择同一个文件时接续
+    if let Some(session) = &session {
+        if result.is_ok() || matches!(result, Err(ChuaError::Aborted)) {
+            session.remove();
+        }
+    }
+
+    match result {
+        Ok(id) => Ok(UploadResult {
+            id: id.to_string(),
+            duplicated,
+            size,
+            elapsed: js_sys::Date::now() - start,
+        }),
+        Err(e) => Err(error::to_js(e)),
     }
 }
+
+/// 在 Worker 中计算 `blob` 的 md5，不阻塞页面。`onProgress` 的参数为 `(loaded, total)`
+#[wasm_bindgen(skip_typescript)]
+pub async fn md5(
+    blob: web_sys::Blob,
+    on_progress: Option<js_sys::Function>,
+) -> Result<String, JsValue> {
+    hash::md5(&blob, on_progress.as_ref()).await
+}
+
+/// 等待上传完成，同时消费事件回调进度。`session` 不为空时在初始化后记录上传会话
+async fn track(
+    future: impl Future<Output = ChuaResult<Uuid>>,
+    mut events: UploadEvents,
+    on_progress: Option<js_sys::Function>,
+    start: f64,
+    session: Option<Session>,
+) -> (ChuaResult<Uuid>, bool) {
+    let progress = async move {
+        let mut duplicated = false;
+        let mut progress = Progress {
+            loaded: 0.0,
+            total: 0.0,
+            chunks: 0,
+            total_chunks: 0,
+            throughput: 0.0,
+        };
+
+        while let Some(event) = events.next().await {
+            match event {
+                UploadEvent::Initialized {
+                    id,
+                    duplicated: d,
+                    size,
+                    chunk_size,
+                } => {
+                    duplicated = d;
+                    progress.total = size as f64;
+                    if chunk_size > 0 {
+                        progress.total_chunks = ((size + chunk_size - 1) / chunk_size) as u32;
+                    }
+
+                    if let (Some(session), false) = (&session, d) {
+                        session.save(id.to_string(), chunk_size);
+                    }
+                }
+                UploadEvent::Resumed {
+                    size,
+                    chunk_size,
+                    received_size,
+                    received_chunks,
+                    ..
+                } => {
+                    progress.total = size as f64;
+                    progress.loaded = received_size as f64;
+                    progress.chunks = received_chunks as u32;
+                    if chunk_size > 0 {
+                        progress.total_chunks = ((size + chunk_size - 1) / chunk_size) as u32;
+                    }
+                }
+                UploadEvent::ChunkAcknowledged { size, .. } => {
+                    progress.loaded += size as f64;
+                    progress.chunks += 1;
+                }
+                _ => continue,
+            }
+
+            let elapsed = (js_sys::Date::now() - start) / 1000.0;
+            if elapsed > 0.0 {
+                progress.throughput = progress.loaded / elapsed;
+            }
+
+            if let Some(callback) = &on_progress {
+                let _ = callback.call1(&JsValue::NULL, &JsValue::from(progress));
+            }
+        }
+
+        duplicated
+    };
+
+    futures::join!(future, progress)
+}
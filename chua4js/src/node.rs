@@ -0,0 +1,38 @@
+use js_sys::Promise;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::JsFuture;
+use web_sys::Blob;
+
+#[wasm_bindgen(module = "fs")]
+extern "C" {
+    /// Node.js 19.8 开始提供，旧版本中调用会抛出异常
+    #[wasm_bindgen(catch, js_name = openAsBlob)]
+    fn open_as_blob(path: &str) -> Result<Promise, JsValue>;
+}
+
+#[wasm_bindgen(module = "fs/promises")]
+extern "C" {
+    #[wasm_bindgen(catch, js_name = readFile)]
+    fn read_file(path: &str) -> Result<Promise, JsValue>;
+}
+
+/// 把本地文件作为 `Blob` 打开。优先用 `fs.openAsBlob`，分片时才从磁盘读取；
+/// 旧版本的 Node.js 只能把整个文件读入内存
+pub async fn open(path: &str) -> Result<Blob, JsValue> {
+    match open_as_blob(path) {
+        Ok(promise) => JsFuture::from(promise).await?.dyn_into(),
+        Err(_) => {
+            let buffer = JsFuture::from(read_file(path)?).await?;
+            Blob::new_with_buffer_source_sequence(&js_sys::Array::of1(&buffer))
+        }
+    }
+}
+
+/// 路径中的文件名，Windows 的路径也可能用 `\` 分隔
+pub fn file_name(path: &str) -> &str {
+    match path.rfind(|c| c == '/' || c == '\\') {
+        Some(index) => &path[index + 1..],
+        None => path,
+    }
+}
@@ -0,0 +1,129 @@
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{Blob, Storage};
+
+/// localStorage 中记录的键前缀
+const KEY_PREFIX: &str = "chua:upload:";
+
+/// 计算指纹时读取的文件头尾长度
+const SAMPLE_SIZE: f64 = 64.0 * 1024.0;
+
+/// 保存在 localStorage 中的上传会话，用于刷新页面后接续上传
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Record {
+    pub base_url: String,
+    pub name: String,
+    pub size: f64,
+    pub last_modified: f64,
+    pub fingerprint: String,
+    pub id: String,
+    pub chunk_size: f64,
+}
+
+/// 同一个文件再次被选中时用来找到之前的上传会话
+#[derive(Debug, Clone)]
+pub struct Session {
+    key: String,
+    base_url: String,
+    name: String,
+    size: f64,
+    last_modified: f64,
+    fingerprint: String,
+}
+
+impl Session {
+    /// 按文件名、大小、修改时间和文件头尾各 64KiB 的内容计算指纹。
+    /// 不是 `File` 的数据源没有修改时间，`last_modified` 传 0
+    pub async fn new(
+        base_url: &str,
+        file: &Blob,
+        name: String,
+        last_modified: f64,
+    ) -> Result<Self, JsValue> {
+        let size = file.size();
+
+        let mut context = md5::Context::new();
+        context.consume(base_url.as_bytes());
+        context.consume(name.as_bytes());
+        context.consume(size.to_le_bytes());
+        context.consume(last_modified.to_le_bytes());
+        context.consume(read(file, 0.0, SAMPLE_SIZE.min(size)).await?);
+        if size > SAMPLE_SIZE {
+            context.consume(read(file, (size - SAMPLE_SIZE).max(SAMPLE_SIZE), size).await?);
+        }
+
+        let fingerprint = format!("{:x}", context.compute());
+
+        Ok(Self {
+            key: format!("{}{}", KEY_PREFIX, fingerprint),
+            base_url: base_url.to_string(),
+            name,
+            size,
+            last_modified,
+            fingerprint,
+        })
+    }
+
+    /// 读取之前保存的记录，文件信息对不上时忽略
+    pub fn load(&self) -> Option<Record> {
+        let json = storage()?.get_item(&self.key).ok()??;
+
+        let record: Record = match serde_json::from_str(&json) {
+            Ok(record) => record,
+            Err(_) => {
+                self.remove();
+                return None;
+            }
+        };
+
+        if record.base_url == self.base_url
+            && record.name == self.name
+            && record.size == self.size
+            && record.last_modified == self.last_modified
+            && record.fingerprint == self.fingerprint
+        {
+            Some(record)
+        } else {
+            None
+        }
+    }
+
+    /// 记录服务端分配的文件ID和分片大小
+    pub fn save(&self, id: String, chunk_size: u64) {
+        let record = Record {
+            base_url: self.base_url.clone(),
+            name: self.name.clone(),
+            size: self.size,
+            last_modified: self.last_modified,
+            fingerprint: self.fingerprint.clone(),
+            id,
+            chunk_size: chunk_size as f64,
+        };
+
+        if let (Some(storage), Ok(json)) = (storage(), serde_json::to_string(&record)) {
+            // 存储空间已满或被禁用时只是无法接续，不影响上传
+            let _ = storage.set_item(&self.key, &json);
+        }
+    }
+
+    /// 上传完成或放弃后删除记录
+    pub fn remove(&self) {
+        if let Some(storage) = storage() {
+            let _ = storage.remove_item(&self.key);
+        }
+    }
+}
+
+fn storage() -> Option<Storage> {
+    web_sys::window()?.local_storage().ok()?
+}
+
+/// 读取文件中 `start..end` 这一段
+async fn read(file: &Blob, start: f64, end: f64) -> Result<Vec<u8>, JsValue> {
+    let blob = file.slice_with_f64_and_f64(start, end)?;
+    let buffer = JsFuture::from(blob.array_buffer()).await?;
+
+    Ok(js_sys::Uint8Array::new(&buffer).to_vec())
+}
@@ -0,0 +1,84 @@
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::JsFuture;
+use web_sys::Blob;
+
+#[wasm_bindgen]
+extern "C" {
+    /// WHATWG 的 `ReadableStream`，只用到 `getReader`
+    type ReadableStream;
+
+    #[wasm_bindgen(method, js_name = getReader)]
+    fn get_reader(this: &ReadableStream) -> StreamReader;
+
+    /// `ReadableStreamDefaultReader`
+    type StreamReader;
+
+    #[wasm_bindgen(method)]
+    fn read(this: &StreamReader) -> js_sys::Promise;
+
+    #[wasm_bindgen(method, js_name = releaseLock)]
+    fn release_lock(this: &StreamReader);
+}
+
+/// 把上传的数据源转换为 `Blob`，之后都按 `Blob` 分片上传。
+///
+/// 支持 `File`/`Blob`、`ArrayBuffer`、`Uint8Array` 等 `ArrayBufferView`，以及 `ReadableStream`。
+/// 初始化时必须知道文件大小，所以流会先全部读出来，由浏览器决定放在内存还是磁盘上。
+pub async fn to_blob(source: JsValue) -> Result<Blob, JsValue> {
+    if let Some(blob) = source.dyn_ref::<Blob>() {
+        return Ok(blob.clone());
+    }
+
+    if source.is_instance_of::<js_sys::ArrayBuffer>() || js_sys::ArrayBuffer::is_view(&source) {
+        return Blob::new_with_buffer_source_sequence(&js_sys::Array::of1(&source));
+    }
+
+    if is_readable_stream(&source) {
+        return read_stream(source.unchecked_ref()).await;
+    }
+
+    Err(js_sys::TypeError::new(
+        "The source must be a Blob, ArrayBuffer, ArrayBufferView or ReadableStream",
+    )
+    .into())
+}
+
+/// 没有 `ReadableStream` 的环境里不能直接用 `instanceof`
+fn is_readable_stream(source: &JsValue) -> bool {
+    source.is_object()
+        && js_sys::Reflect::get(source, &"getReader".into())
+            .map(|f| f.is_function())
+            .unwrap_or(false)
+}
+
+/// 读出流中的所有数据块，拼成一个 `Blob`
+async fn read_stream(stream: &ReadableStream) -> Result<Blob, JsValue> {
+    let reader = stream.get_reader();
+    let parts = js_sys::Array::new();
+
+    let result = async {
+        loop {
+            let result = JsFuture::from(reader.read()).await?;
+
+            if js_sys::Reflect::get(&result, &"done".into())?.is_truthy() {
+                break;
+            }
+
+            let value = js_sys::Reflect::get(&result, &"value".into())?;
+            if value.is_undefined() {
+                continue;
+            }
+
+            // 数据块可以是 Uint8Array、ArrayBuffer、Blob 或字符串，都是 Blob 的合法组成部分
+            parts.push(&value);
+        }
+
+        Blob::new_with_u8_array_sequence(&parts)
+    }
+    .await;
+
+    reader.release_lock();
+
+    result
+}
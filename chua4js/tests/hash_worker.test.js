@@ -0,0 +1,85 @@
+// 在 Node.js 中运行浏览器里计算 MD5 的 `src/hash_worker.js`，与 `crypto.createHash("md5")` 比较。
+// 用 vm 模块给脚本一个带 `self` 和 `FileReaderSync` 替身的全局环境，不需要构建 wasm 包。
+//
+//   node --test tests/
+const { test } = require("node:test");
+const assert = require("node:assert");
+const crypto = require("node:crypto");
+const fs = require("node:fs");
+const path = require("node:path");
+const vm = require("node:vm");
+
+const SOURCE = fs.readFileSync(path.join(__dirname, "..", "src", "hash_worker.js"), "utf8");
+
+/** 加载一个新的 Worker。脚本的状态是全局的，每次计算都要重新加载 */
+function loadWorker() {
+    const replies = [];
+    const self = { postMessage: (message) => replies.push(message) };
+
+    // 主线程发送的是 Blob 分片，这里直接发送 Uint8Array
+    class FileReaderSync {
+        readAsArrayBuffer(data) {
+            return data.buffer.slice(data.byteOffset, data.byteOffset + data.byteLength);
+        }
+    }
+
+    vm.runInNewContext(SOURCE, { self, FileReaderSync });
+
+    return {
+        replies,
+        post: (data) => self.onmessage({ data }),
+    };
+}
+
+/** 把 `data` 按 `sizes` 循环切片发送给 Worker，检查每次回复的进度，返回计算出的 MD5 */
+function workerMd5(data, sizes = [data.length]) {
+    const worker = loadWorker();
+    const expected = [];
+
+    for (let offset = 0, i = 0; offset < data.length; i++) {
+        const end = Math.min(offset + sizes[i % sizes.length], data.length);
+        worker.post(data.subarray(offset, end));
+        expected.push(end);
+        offset = end;
+    }
+
+    worker.post(null);
+
+    // 回复来自 vm 的上下文，原型不同，只比较字段
+    const [last] = worker.replies.splice(-1);
+    assert.deepStrictEqual(
+        worker.replies.map((reply) => reply.loaded),
+        expected,
+    );
+    assert.ok(last.md5, JSON.stringify(last));
+    return last.md5;
+}
+
+function md5(data) {
+    return crypto.createHash("md5").update(data).digest("hex");
+}
+
+test("matches crypto around the padding boundaries", () => {
+    // 55 字节时长度刚好能放进最后一块，56 字节时需要多一块，64 和 65 跨过一整块
+    for (const length of [0, 1, 55, 56, 63, 64, 65, 119, 120, 128]) {
+        const data = crypto.randomBytes(length);
+        assert.strictEqual(workerMd5(data), md5(data), `length ${length}`);
+    }
+});
+
+test("matches crypto when the file is sent in slices", () => {
+    const data = crypto.randomBytes(100 * 1024 + 7);
+
+    // 各种大小交替，分片边界落在块的不同位置，包括空的分片
+    assert.strictEqual(workerMd5(data, [1, 63, 64, 65, 0, 1000, 4096]), md5(data));
+    assert.strictEqual(workerMd5(data, [64 * 1024]), md5(data));
+});
+
+test("reports read errors", () => {
+    const worker = loadWorker();
+
+    worker.post(undefined);
+
+    assert.strictEqual(worker.replies.length, 1);
+    assert.ok(worker.replies[0].error);
+});
@@ -0,0 +1,188 @@
+// 在 Node.js 中用 `uploadFile` 上传本地文件，分别走 `fs.openAsBlob` 和旧版本的 `readFile` 两条路径。
+// 服务端是进程内按协议应答的 HTTP 服务，收到的分片按序号拼接后与原文件比较。
+//
+//   wasm-pack build --target nodejs --out-dir pkg-node -- --features node
+//   node --test tests/
+//
+// 需要 Node.js 19.8 以上（`fs.openAsBlob`）。
+const { test } = require("node:test");
+const assert = require("node:assert");
+const crypto = require("node:crypto");
+const fs = require("node:fs");
+const fsp = require("node:fs/promises");
+const http = require("node:http");
+const os = require("node:os");
+const path = require("node:path");
+
+const PKG = path.join(__dirname, "..", "pkg-node");
+const MAX_CHUNK_SIZE = 1000;
+
+/** 启动服务，返回 `{ baseUrl, files, close }`，`files` 中是完成的文件内容 */
+async function startServer() {
+    const uploads = new Map();
+    const files = new Map();
+
+    const server = http.createServer(async (req, res) => {
+        const body = [];
+        for await (const data of req) {
+            body.push(data);
+        }
+
+        const reply = (value) => {
+            res.setHeader("Content-Type", "application/json");
+            res.end(JSON.stringify(value));
+        };
+
+        const segments = req.url.split("/").filter((s) => s);
+
+        if (req.method === "GET" && segments[0] === "limits") {
+            return reply({
+                max_file_size: 1024 * 1024,
+                max_chunk_size: MAX_CHUNK_SIZE,
+                version: 1,
+                capabilities: [],
+            });
+        }
+
+        if (req.method === "POST" && segments.length === 1) {
+            const param = JSON.parse(Buffer.concat(body).toString());
+            const id = crypto.randomUUID();
+            uploads.set(id, { param, chunks: new Map() });
+            return reply({ result: "Ok", id, duplicated: false, version: 1, capabilities: [] });
+        }
+
+        if (req.method === "PUT" && segments.length === 3) {
+            // 借用 fetch 的 Response 解析 multipart
+            const form = await new Response(Buffer.concat(body), {
+                headers: { "Content-Type": req.headers["content-type"] },
+            }).formData();
+            const chunk = Buffer.from(await form.get("chunk").arrayBuffer());
+
+            uploads.get(segments[1]).chunks.set(Number(segments[2]), chunk);
+            return reply({ result: "Ok" });
+        }
+
+        if (req.method === "POST" && segments.length === 2) {
+            const { param, chunks } = uploads.get(segments[1]);
+            const count = Math.ceil(param.size / param.chunk_size);
+            const ordered = [];
+
+            for (let i = 0; i < count; i++) {
+                if (!chunks.has(i)) {
+                    return reply({ result: "Err", error: { type: "Other", detail: `missing chunk ${i}` } });
+                }
+                ordered.push(chunks.get(i));
+            }
+
+            files.set(segments[1], { param, data: Buffer.concat(ordered) });
+            return reply({ result: "Ok" });
+        }
+
+        res.statusCode = 404;
+        res.end();
+    });
+
+    await new Promise((resolve) => server.listen(0, "127.0.0.1", resolve));
+
+    return {
+        baseUrl: `http://127.0.0.1:${server.address().port}/`,
+        files,
+        close: () => new Promise((resolve) => server.close(resolve)),
+    };
+}
+
+/** 写一个跨多个分片、最后一片不满的临时文件 */
+async function tempFile() {
+    const dir = await fsp.mkdtemp(path.join(os.tmpdir(), "chua4js-"));
+    const file = path.join(dir, "sample.bin");
+    await fsp.writeFile(file, crypto.randomBytes(MAX_CHUNK_SIZE * 4 + 123));
+    return file;
+}
+
+/**
+ * 重新加载 pkg-node。生成的胶水代码可能在加载时就取出 `fs` 中的函数，
+ * 所以要在加载之前替换，`patch` 返回恢复原状的函数
+ */
+function loadPackage(patch) {
+    for (const key of Object.keys(require.cache)) {
+        if (key.startsWith(PKG)) {
+            delete require.cache[key];
+        }
+    }
+
+    const restore = patch();
+    return { chua: require(PKG), restore };
+}
+
+async function uploadAndCheck(chua) {
+    const server = await startServer();
+    const file = await tempFile();
+
+    try {
+        const result = await chua.uploadFile(server.baseUrl, file, 0, 2, { hash: true });
+
+        const uploaded = server.files.get(result.id);
+        assert.ok(uploaded, "the upload was not completed");
+        // 不经过被替换的 readFile
+        assert.deepStrictEqual(uploaded.data, fs.readFileSync(file));
+        assert.strictEqual(uploaded.param.name, "sample.bin");
+        assert.strictEqual(uploaded.param.chunk_size, MAX_CHUNK_SIZE);
+        assert.strictEqual(
+            uploaded.param.md5,
+            crypto.createHash("md5").update(uploaded.data).digest("hex"),
+        );
+        assert.strictEqual(result.size, uploaded.data.length);
+    } finally {
+        await server.close();
+        await fsp.rm(path.dirname(file), { recursive: true, force: true });
+    }
+}
+
+test("uploads with fs.openAsBlob", async () => {
+    let opened = 0;
+    let read = 0;
+
+    const { chua, restore } = loadPackage(() => {
+        const { openAsBlob } = fs;
+        const { readFile } = fsp;
+        fs.openAsBlob = (...args) => (opened++, openAsBlob(...args));
+        fsp.readFile = (...args) => (read++, readFile(...args));
+        return () => {
+            fs.openAsBlob = openAsBlob;
+            fsp.readFile = readFile;
+        };
+    });
+
+    try {
+        await uploadAndCheck(chua);
+    } finally {
+        restore();
+    }
+
+    assert.strictEqual(opened, 1);
+    assert.strictEqual(read, 0);
+});
+
+test("falls back to readFile without fs.openAsBlob", async () => {
+    let read = 0;
+
+    const { chua, restore } = loadPackage(() => {
+        const { openAsBlob } = fs;
+        const { readFile } = fsp;
+        // 模拟 19.8 之前的 Node.js
+        fs.openAsBlob = undefined;
+        fsp.readFile = (...args) => (read++, readFile(...args));
+        return () => {
+            fs.openAsBlob = openAsBlob;
+            fsp.readFile = readFile;
+        };
+    });
+
+    try {
+        await uploadAndCheck(chua);
+    } finally {
+        restore();
+    }
+
+    assert.strictEqual(read, 1);
+});
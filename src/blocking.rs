@@ -0,0 +1,100 @@
+//! 同步接口，内部自带一个 tokio 运行时，调用方无需处理 async。
+//!
+//! 不要在 tokio 运行时内部调用这些函数。C 和 Java 绑定也通过 [`handle`] 使用同一个运行时。
+
+#[cfg(feature = "preprocess")]
+use crate::ImageOptions;
+use crate::{ChuaError, ChuaResult, ClientOptions, FileInfo};
+use lazy_static::lazy_static;
+use reqwest::IntoUrl;
+use std::future::Future;
+use std::path::Path;
+use tokio::runtime::{Handle, Runtime};
+use uuid::Uuid;
+
+lazy_static! {
+    static ref RUNTIME: Result<Runtime, String> = tokio::runtime::Builder::new()
+        .threaded_scheduler()
+        .enable_all()
+        .build()
+        .map_err(|e| e.to_string());
+}
+
+/// 同步接口共用的运行时，可以用它在后台执行上传。运行时创建失败时返回错误
+pub fn handle() -> ChuaResult<&'static Handle> {
+    match RUNTIME.as_ref() {
+        Ok(runtime) => Ok(runtime.handle()),
+        Err(e) => Err(ChuaError::Other(e.clone())),
+    }
+}
+
+fn block_on<F: Future>(future: F) -> ChuaResult<F::Output> {
+    Ok(handle()?.block_on(future))
+}
+
+/// 同步版本的 [`crate::upload`]
+pub fn upload(
+    base_url: impl IntoUrl,
+    path: impl AsRef<Path>,
+    chunk_size: u64,
+    parallel: usize,
+) -> ChuaResult<Uuid> {
+    block_on(crate::upload(base_url, path, chunk_size, parallel))?
+}
+
+/// 同步版本的 [`crate::upload_with_options`]
+pub fn upload_with_options(
+    base_url: impl IntoUrl,
+    path: impl AsRef<Path>,
+    chunk_size: u64,
+    parallel: usize,
+    options: &ClientOptions,
+) -> ChuaResult<Uuid> {
+    block_on(crate::upload_with_options(
+        base_url, path, chunk_size, parallel, options,
+    ))?
+}
+
+/// 同步版本的 [`crate::upload_with_info`]
+pub fn upload_with_info(
+    base_url: impl IntoUrl,
+    path: impl AsRef<Path>,
+    chunk_size: u64,
+    parallel: usize,
+    info: FileInfo,
+    options: &ClientOptions,
+) -> ChuaResult<Uuid> {
+    block_on(crate::upload_with_info(
+        base_url, path, chunk_size, parallel, info, options,
+    ))?
+}
+
+/// 同步版本的 [`crate::upload_image`]
+#[cfg(feature = "preprocess")]
+pub fn upload_image(
+    base_url: impl IntoUrl,
+    path: impl AsRef<Path>,
+    chunk_size: u64,
+    parallel: usize,
+    image: &ImageOptions,
+    info: FileInfo,
+    options: &ClientOptions,
+) -> ChuaResult<Uuid> {
+    block_on(crate::upload_image(
+        base_url, path, chunk_size, parallel, image, info, options,
+    ))?
+}
+
+/// 同步版本的 [`crate::download`]
+pub fn download(
+    url: impl IntoUrl,
+    path: impl AsRef<Path>,
+    chunk_size: u64,
+    parallel: usize,
+    md5: Option<&str>,
+    options: &ClientOptions,
+) -> ChuaResult<u64> {
+    block_on(crate::download(
+        url, path, chunk_size, parallel, md5, options,
+    ))?
+}
@@ -0,0 +1,71 @@
+use futures::future::{select, Either, FutureExt, Shared};
+use futures_channel::oneshot;
+use std::fmt;
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// 取消上传的令牌，克隆出的令牌共享同一个状态。
+///
+/// 取消后正在上传的分片会被中断，并通知服务端丢弃已上传的分片，上传返回
+/// [`ChuaError::Aborted`](crate::ChuaError::Aborted)。
+#[derive(Clone)]
+pub struct CancelToken {
+    canceled: Arc<AtomicBool>,
+    sender: Arc<Mutex<Option<oneshot::Sender<()>>>>,
+    receiver: Shared<oneshot::Receiver<()>>,
+}
+
+impl CancelToken {
+    pub fn new() -> Self {
+        let (sender, receiver) = oneshot::channel();
+
+        Self {
+            canceled: Arc::new(AtomicBool::new(false)),
+            sender: Arc::new(Mutex::new(Some(sender))),
+            receiver: receiver.shared(),
+        }
+    }
+
+    /// 取消上传，重复调用没有影响
+    pub fn cancel(&self) {
+        self.canceled.store(true, Ordering::SeqCst);
+
+        if let Ok(mut sender) = self.sender.lock() {
+            if let Some(sender) = sender.take() {
+                let _ = sender.send(());
+            }
+        }
+    }
+
+    /// 是否已经取消
+    pub fn is_canceled(&self) -> bool {
+        self.canceled.load(Ordering::SeqCst)
+    }
+
+    /// 运行 `f`，在它完成之前被取消时返回 `None`
+    pub(crate) async fn run<F: Future>(&self, f: F) -> Option<F::Output> {
+        if self.is_canceled() {
+            return None;
+        }
+
+        match select(Box::pin(f), self.receiver.clone()).await {
+            Either::Left((output, _)) => Some(output),
+            Either::Right(_) => None,
+        }
+    }
+}
+
+impl Default for CancelToken {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for CancelToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CancelToken")
+            .field("canceled", &self.is_canceled())
+            .finish()
+    }
+}
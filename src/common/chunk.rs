@@ -1,4 +1,5 @@
 use std::ops::Range;
+use std::sync::{Arc, Mutex};
 
 #[derive(Debug)]
 pub(crate) struct Chunk<T> {
@@ -12,33 +13,72 @@ pub(crate) struct ChunkIterator {
     chunk_size: u64,
     remainder: u64,
 
+    // 第一个分片在文件中的起始位置
+    offset: u64,
+
+    // 只返回这些序号的分片，为 None 时返回全部
+    only: Option<Vec<Range<usize>>>,
+
     // current chunk index
     index: usize,
 }
 
 impl ChunkIterator {
     pub fn new(size: u64, chunk_size: u64) -> Self {
-        let remainder = size % chunk_size;
+        Self::with_range(0..size, chunk_size)
+    }
 
-        let chunk_count = (size / chunk_size) as usize + if remainder > 0 { 1 } else { 0 };
+    /// 只切分文件中 `range` 这一段，分片序号从 0 开始，位置仍是在整个文件中的位置
+    pub fn with_range(range: Range<u64>, chunk_size: u64) -> Self {
+        let size = range.end - range.start;
+
+        // 分片大小为 0 时没有可以返回的分片
+        let (chunk_count, remainder) = if chunk_size == 0 {
+            (0, 0)
+        } else {
+            let remainder = size % chunk_size;
+            (
+                (size / chunk_size) as usize + if remainder > 0 { 1 } else { 0 },
+                remainder,
+            )
+        };
 
         Self {
             chunk_count,
             chunk_size,
             remainder,
+            offset: range.start,
+            only: None,
             index: 0,
         }
     }
+
+    /// 只返回序号在 `only` 中的分片，用于接续上传时跳过服务端已收到的分片
+    pub fn only(mut self, only: Vec<Range<usize>>) -> Self {
+        self.only = Some(only);
+        self
+    }
+
+    fn skipped(&self, index: usize) -> bool {
+        match &self.only {
+            Some(only) => !only.iter().any(|range| range.contains(&index)),
+            None => false,
+        }
+    }
 }
 
 impl Iterator for ChunkIterator {
     type Item = (usize, Range<u64>);
 
     fn next(&mut self) -> Option<Self::Item> {
+        while self.index < self.chunk_count && self.skipped(self.index) {
+            self.index += 1;
+        }
+
         let cur = self.index;
 
         if cur < self.chunk_count {
-            let start = self.index as u64 * self.chunk_size;
+            let start = self.offset + self.index as u64 * self.chunk_size;
 
             let result = if self.remainder > 0 && cur == self.chunk_count - 1 {
                 (cur, start..start + self.remainder)
@@ -55,3 +95,80 @@ impl Iterator for ChunkIterator {
         }
     }
 }
+
+/// 多个上传任务共享的分片队列，每个任务从中领取下一个要上传的分片
+#[derive(Debug, Clone)]
+pub(crate) struct ChunkQueue(Arc<Mutex<ChunkIterator>>);
+
+impl ChunkQueue {
+    pub fn new(range: Range<u64>, chunk_size: u64) -> Self {
+        Self(Arc::new(Mutex::new(ChunkIterator::with_range(
+            range, chunk_size,
+        ))))
+    }
+
+    /// 只上传 `missing` 中的分片
+    pub fn missing(range: Range<u64>, chunk_size: u64, missing: Vec<Range<usize>>) -> Self {
+        Self(Arc::new(Mutex::new(
+            ChunkIterator::with_range(range, chunk_size).only(missing),
+        )))
+    }
+
+    pub fn next(&self) -> Option<(usize, Range<u64>)> {
+        match self.0.lock() {
+            Ok(mut iter) => iter.next(),
+            Err(_) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_with_a_short_tail() {
+        let chunks: Vec<_> = ChunkIterator::new(10, 4).collect();
+
+        assert_eq!(chunks, vec![(0, 0..4), (1, 4..8), (2, 8..10)]);
+    }
+
+    #[test]
+    fn splits_exact_multiples_without_a_tail() {
+        let chunks: Vec<_> = ChunkIterator::new(8, 4).collect();
+
+        assert_eq!(chunks, vec![(0, 0..4), (1, 4..8)]);
+    }
+
+    #[test]
+    fn keeps_file_positions_for_unaligned_ranges() {
+        let chunks: Vec<_> = ChunkIterator::with_range(5..15, 4).collect();
+
+        assert_eq!(chunks, vec![(0, 5..9), (1, 9..13), (2, 13..15)]);
+    }
+
+    #[test]
+    fn returns_nothing_for_empty_ranges_or_zero_chunk_size() {
+        assert_eq!(ChunkIterator::new(0, 4).count(), 0);
+        assert_eq!(ChunkIterator::with_range(7..7, 4).count(), 0);
+        assert_eq!(ChunkIterator::new(10, 0).count(), 0);
+    }
+
+    #[test]
+    fn only_returns_selected_chunks() {
+        let chunks: Vec<_> = ChunkIterator::new(10, 2)
+            .only(vec![3..5, 0..1, 4..5])
+            .collect();
+
+        assert_eq!(chunks, vec![(0, 0..2), (3, 6..8), (4, 8..10)]);
+    }
+
+    #[test]
+    fn only_includes_the_tail_chunk() {
+        let chunks: Vec<_> = ChunkIterator::with_range(3..13, 4)
+            .only(vec![2..3, 5..6])
+            .collect();
+
+        assert_eq!(chunks, vec![(2, 11..13)]);
+    }
+}
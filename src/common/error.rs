@@ -1,3 +1,7 @@
+use crate::{
+    AbortError, AppendError, Capability, CompleteError, InitializeError, StatusError,
+    UploadChunkError,
+};
 use thiserror::Error as TError;
 
 #[derive(TError, Debug)]
@@ -24,10 +28,77 @@ pub enum ChuaError {
     #[error(transparent)]
     Utf8(#[from] std::string::FromUtf8Error),
 
+    #[cfg(all(feature = "preprocess", not(target_arch = "wasm32")))]
+    #[error(transparent)]
+    Image(#[from] image::ImageError),
+
+    #[error("initialize failed: {0}")]
+    Initialize(InitializeError),
+
+    #[error("append failed: {0}")]
+    Append(AppendError),
+
+    #[error("upload chunk failed: {0}")]
+    UploadChunk(UploadChunkError),
+
+    #[error("complete failed: {0}")]
+    Complete(CompleteError),
+
+    #[error("query status failed: {0}")]
+    Status(StatusError),
+
+    #[error("abort failed: {0}")]
+    Abort(AbortError),
+
+    #[error("upload aborted")]
+    Aborted,
+
+    /// 接续上传时本地文件的大小与服务端记录的不一致
+    #[error("the file size ({local}) does not match the upload ({remote})")]
+    SizeMismatch { local: u64, remote: u64 },
+
+    /// 下载完成后文件的 md5 与给出的不一致，下载的文件已被删除
+    #[error("the downloaded file's MD5 ({actual}) does not match {expected}")]
+    Md5Mismatch { expected: String, actual: String },
+
+    /// 服务端没有协商这项能力，相应的请求没有发出
+    #[error("the server does not support {0:?}")]
+    Unsupported(Capability),
+
+    /// 服务端返回了非 2xx 的状态码
+    #[error("{method} {url} returned {status}")]
+    HttpStatus {
+        method: String,
+        url: String,
+        status: u16,
+    },
+
+    /// fetch 请求失败或超时，没有拿到响应
+    #[cfg(target_arch = "wasm32")]
+    #[error("{0}")]
+    Network(String),
+
     #[error("{0}")]
     Other(String),
 }
 
+macro_rules! impl_from_protocol_error {
+    ($error:ident, $variant:ident) => {
+        impl From<$error> for ChuaError {
+            fn from(e: $error) -> Self {
+                Self::$variant(e)
+            }
+        }
+    };
+}
+
+impl_from_protocol_error!(InitializeError, Initialize);
+impl_from_protocol_error!(AppendError, Append);
+impl_from_protocol_error!(UploadChunkError, UploadChunk);
+impl_from_protocol_error!(CompleteError, Complete);
+impl_from_protocol_error!(StatusError, Status);
+impl_from_protocol_error!(AbortError, Abort);
+
 impl From<String> for ChuaError {
     fn from(s: String) -> Self {
         Self::Other(s)
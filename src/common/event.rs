@@ -0,0 +1,145 @@
+use futures::task::{Context, Poll};
+use futures::Stream;
+use futures_channel::mpsc;
+use std::pin::Pin;
+use uuid::Uuid;
+
+/// 上传过程中产生的事件
+#[derive(Debug, Clone)]
+pub enum UploadEvent {
+    /// 初始化完成
+    Initialized {
+        /// 文件ID
+        id: Uuid,
+
+        /// 是否已上传过
+        duplicated: bool,
+
+        /// 要上传的字节数
+        size: u64,
+
+        /// 分片大小
+        chunk_size: u64,
+    },
+
+    /// 接续之前的上传，服务端已收到部分分片
+    Resumed {
+        /// 文件ID
+        id: Uuid,
+
+        /// 要上传的字节数
+        size: u64,
+
+        /// 分片大小
+        chunk_size: u64,
+
+        /// 服务端已收到的字节数
+        received_size: u64,
+
+        /// 服务端已收到的分片数
+        received_chunks: usize,
+    },
+
+    /// 开始上传一个分片
+    ChunkStarted { index: usize, size: u64 },
+
+    /// 服务端已确认收到一个分片
+    ChunkAcknowledged { index: usize, size: u64 },
+
+    /// 分片上传失败，即将进行第 `attempt` 次重试
+    ChunkRetried {
+        index: usize,
+        attempt: usize,
+        error: String,
+    },
+
+    /// 分片重试后仍然失败
+    ChunkFailed { index: usize, error: String },
+
+    /// 所有分片已发送，正在请求完成上传
+    Completing,
+
+    /// 上传完成
+    Completed { id: Uuid },
+}
+
+/// 下载过程中产生的事件
+#[derive(Debug, Clone)]
+pub enum DownloadEvent {
+    /// 开始下载
+    Started {
+        /// 文件大小
+        size: u64,
+
+        /// 分片大小
+        chunk_size: u64,
+
+        /// 续传时之前已下载的字节数
+        downloaded_size: u64,
+    },
+
+    /// 一个分片已写入文件
+    ChunkDownloaded { index: usize, size: u64 },
+
+    /// 分片下载失败，即将进行第 `attempt` 次重试
+    ChunkRetried {
+        index: usize,
+        attempt: usize,
+        error: String,
+    },
+
+    /// 所有分片已下载，正在校验 md5
+    Verifying,
+
+    /// 下载完成
+    Completed { size: u64 },
+}
+
+/// 事件流，上传或下载结束后流随之结束
+#[derive(Debug)]
+pub struct EventStream<E>(mpsc::UnboundedReceiver<E>);
+
+/// 上传事件流
+pub type UploadEvents = EventStream<UploadEvent>;
+
+/// 下载事件流
+pub type DownloadEvents = EventStream<DownloadEvent>;
+
+impl<E> Stream for EventStream<E> {
+    type Item = E;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.0).poll_next(cx)
+    }
+}
+
+/// 事件的发送端，没有订阅者时直接丢弃事件
+#[derive(Debug)]
+pub(crate) struct EventSender<E = UploadEvent>(Option<mpsc::UnboundedSender<E>>);
+
+impl<E> Clone for EventSender<E> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<E> Default for EventSender<E> {
+    fn default() -> Self {
+        Self(None)
+    }
+}
+
+impl<E> EventSender<E> {
+    pub(crate) fn channel() -> (Self, EventStream<E>) {
+        let (sender, receiver) = mpsc::unbounded();
+
+        (Self(Some(sender)), EventStream(receiver))
+    }
+
+    pub(crate) fn emit(&self, event: E) {
+        if let Some(sender) = &self.0 {
+            // 订阅者不再关心事件时忽略错误
+            let _ = sender.unbounded_send(event);
+        }
+    }
+}
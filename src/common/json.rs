@@ -1,4 +1,6 @@
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
 use std::ops::Range;
 use uuid::Uuid;
 
@@ -14,8 +16,59 @@ macro_rules! impl_from_error {
     };
 }
 
+/// 当前的协议版本。旧版本的客户端和服务端不发送版本，视为 0
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// 本实现支持的协议能力
+pub const CAPABILITIES: &[Capability] =
+    &[Capability::Append, Capability::Abort, Capability::Status];
+
+/// 协议的可选能力，双方都支持的能力才会被使用
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum Capability {
+    /// 追加上传，见 [`AppendParam`]
+    Append,
+
+    /// 放弃上传，见 [`AbortResult`]
+    Abort,
+
+    /// 查询上传状态，见 [`StatusResult`]
+    Status,
+
+    /// 对方比自己新，支持的能力自己不认识
+    #[serde(other)]
+    Unknown,
+}
+
+/// 协商双方都支持的能力
+pub fn negotiate_capabilities(ours: &[Capability], theirs: &[Capability]) -> Vec<Capability> {
+    ours.iter()
+        .filter(|c| **c != Capability::Unknown && theirs.contains(c))
+        .copied()
+        .collect()
+}
+
+/// 服务端的限制，由 `GET /limits` 返回，客户端可以在初始化之前据此选择分片大小
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Limits {
+    /// 最大文件尺寸
+    pub max_file_size: u64,
+
+    /// 最大分片大小
+    pub max_chunk_size: u64,
+
+    /// 服务端的协议版本，旧的服务端不返回，视为 0
+    #[serde(default)]
+    pub version: u32,
+
+    /// 服务端支持的能力，没有经过初始化的会话（如接续上传）据此判断能否调用
+    #[serde(default)]
+    pub capabilities: Vec<Capability>,
+}
+
 /// 初始化请求的参数
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct InitializeParam {
     /// 文件大小
     pub size: u64,
@@ -28,6 +81,26 @@ pub struct InitializeParam {
 
     /// md5
     pub md5: String,
+
+    /// 原始文件名，服务端提供下载时用于 `Content-Disposition`
+    #[serde(default)]
+    pub name: String,
+
+    /// MIME 类型，服务端提供下载时用于 `Content-Type`
+    #[serde(default)]
+    pub content_type: String,
+
+    /// 自定义的元数据，服务端原样保存
+    #[serde(default)]
+    pub metadata: HashMap<String, String>,
+
+    /// 客户端的协议版本
+    #[serde(default)]
+    pub version: u32,
+
+    /// 客户端支持的能力
+    #[serde(default)]
+    pub capabilities: Vec<Capability>,
 }
 
 /// 初始化响应的结果
@@ -41,6 +114,14 @@ pub enum InitializeResult {
 
         /// 是否已上传过
         duplicated: bool,
+
+        /// 双方商定的协议版本
+        #[serde(default)]
+        version: u32,
+
+        /// 双方都支持的能力
+        #[serde(default)]
+        capabilities: Vec<Capability>,
     },
     Err {
         /// 错误
@@ -64,6 +145,16 @@ pub enum InitializeError {
 
 impl_from_error!(InitializeError);
 
+impl fmt::Display for InitializeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Size { max } => write!(f, "file size must be between 1 and {} bytes", max),
+            Self::ChunkSize { max } => write!(f, "chunk size must be between 1 and {} bytes", max),
+            Self::Other { detail } => write!(f, "{}", detail),
+        }
+    }
+}
+
 /// 分片上传响应的结果
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(tag = "result")]
@@ -85,6 +176,19 @@ pub enum UploadChunkError {
 
 impl_from_error!(UploadChunkError);
 
+impl fmt::Display for UploadChunkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Size { expected, actual } => write!(
+                f,
+                "chunk should be {} bytes, but {} bytes received",
+                expected, actual
+            ),
+            Self::Other { detail } => write!(f, "{}", detail),
+        }
+    }
+}
+
 /// 完成响应的结果
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(tag = "result")]
@@ -108,3 +212,324 @@ pub enum CompleteError {
 }
 
 impl_from_error!(CompleteError);
+
+impl fmt::Display for CompleteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Incomplete { missing } => write!(f, "chunks {:?} are missing", missing),
+            Self::MD5 { expected, actual } => {
+                write!(f, "md5 mismatch: expected {}, actual {}", expected, actual)
+            }
+            Self::Other { detail } => write!(f, "{}", detail),
+        }
+    }
+}
+
+/// 追加请求的参数，把本地文件 `offset` 之后的内容追加到一个已完成的文件末尾
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AppendParam {
+    /// 服务端已有文件的大小，也是追加部分在本地文件中的起始位置
+    pub offset: u64,
+
+    /// 追加部分的大小
+    pub size: u64,
+
+    /// 分片大小
+    pub chunk_size: u64,
+
+    /// 扩展名
+    pub extension: String,
+
+    /// 已有部分（前 `offset` 字节）的 md5，服务端据此校验前缀是否一致
+    pub prefix_md5: String,
+
+    /// 追加部分的 md5
+    pub md5: String,
+}
+
+/// 追加响应的结果
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "result")]
+pub enum AppendResult {
+    /// 成功，之后按新的分片序号上传追加部分并完成即可
+    Ok,
+    Err {
+        /// 错误
+        error: AppendError,
+    },
+}
+
+/// 追加响应的错误
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "type")]
+pub enum AppendError {
+    /// 文件不存在或尚未完成
+    NotFound,
+
+    /// 已有部分与本地不一致，并给出服务端文件的实际大小
+    Prefix { size: u64 },
+
+    /// 追加后的文件尺寸错误
+    Size { max: u64 },
+
+    /// 分片大小不合适，并给出建议的分片大小
+    ChunkSize { max: u64 },
+
+    /// 其它错误
+    Other { detail: String },
+}
+
+impl_from_error!(AppendError);
+
+impl fmt::Display for AppendError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotFound => write!(f, "file not found or not completed"),
+            Self::Prefix { size } => write!(
+                f,
+                "existing content differs from the local file (remote size: {} bytes)",
+                size
+            ),
+            Self::Size { max } => write!(f, "file size must be between 1 and {} bytes", max),
+            Self::ChunkSize { max } => write!(f, "chunk size must be between 1 and {} bytes", max),
+            Self::Other { detail } => write!(f, "{}", detail),
+        }
+    }
+}
+
+/// 上传的状态
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum UploadState {
+    /// 正在上传分片
+    Uploading,
+
+    /// 正在合并分片
+    Completing,
+
+    /// 已完成
+    Completed,
+}
+
+// 枚举上的 `#[default]` 需要较新的编译器
+#[allow(clippy::derivable_impls)]
+impl Default for UploadState {
+    fn default() -> Self {
+        Self::Uploading
+    }
+}
+
+/// 服务端保存的文件信息和上传进度
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FileStatus {
+    /// 文件大小
+    pub size: u64,
+
+    /// 分片大小
+    pub chunk_size: u64,
+
+    /// 扩展名
+    pub extension: String,
+
+    /// 原始文件名
+    #[serde(default)]
+    pub name: String,
+
+    /// MIME 类型
+    #[serde(default)]
+    pub content_type: String,
+
+    /// 自定义的元数据
+    #[serde(default)]
+    pub metadata: HashMap<String, String>,
+
+    /// 已收到的分片序号区间
+    #[serde(default)]
+    pub received: Vec<Range<usize>>,
+
+    /// 上传的状态
+    #[serde(default)]
+    pub state: UploadState,
+}
+
+impl FileStatus {
+    /// 分片总数
+    // `u64::div_ceil` 需要较新的编译器
+    #[allow(clippy::manual_div_ceil)]
+    pub fn chunk_count(&self) -> usize {
+        if self.chunk_size == 0 {
+            return 0;
+        }
+
+        ((self.size + self.chunk_size - 1) / self.chunk_size) as usize
+    }
+
+    /// 尚未收到的分片序号区间，`received` 可以无序或互相重叠
+    pub fn missing(&self) -> Vec<Range<usize>> {
+        let count = self.chunk_count();
+
+        let mut received = self.received.clone();
+        received.sort_by_key(|range| range.start);
+
+        let mut missing = Vec::new();
+        let mut start = 0;
+
+        for range in received {
+            if range.start >= count {
+                break;
+            }
+            if range.start > start {
+                missing.push(start..range.start);
+            }
+            start = start.max(range.end);
+        }
+
+        if start < count {
+            missing.push(start..count);
+        }
+
+        missing
+    }
+}
+
+/// 状态查询响应的结果，由 `GET /file/{fileId}` 返回
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "result")]
+pub enum StatusResult {
+    Ok(FileStatus),
+    Err { error: StatusError },
+}
+
+/// 状态查询响应的错误
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "type")]
+pub enum StatusError {
+    /// 文件不存在
+    NotFound,
+
+    /// 其它错误
+    Other { detail: String },
+}
+
+impl_from_error!(StatusError);
+
+impl fmt::Display for StatusError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotFound => write!(f, "file not found"),
+            Self::Other { detail } => write!(f, "{}", detail),
+        }
+    }
+}
+
+/// 放弃上传响应的结果，由 `DELETE /file/{fileId}` 返回，服务端会删除已上传的分片
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "result")]
+pub enum AbortResult {
+    Ok,
+    Err { error: AbortError },
+}
+
+/// 放弃上传响应的错误
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "type")]
+pub enum AbortError {
+    /// 上传不存在
+    NotFound,
+
+    /// 其它错误
+    Other { detail: String },
+}
+
+impl_from_error!(AbortError);
+
+impl fmt::Display for AbortError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotFound => write!(f, "upload not found"),
+            Self::Other { detail } => write!(f, "{}", detail),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn negotiation_keeps_common_known_capabilities() {
+        let theirs: Vec<Capability> =
+            serde_json::from_str(r#"["status", "resumable_download", "append"]"#).unwrap();
+
+        assert_eq!(
+            theirs,
+            vec![Capability::Status, Capability::Unknown, Capability::Append]
+        );
+        assert_eq!(
+            negotiate_capabilities(CAPABILITIES, &theirs),
+            vec![Capability::Append, Capability::Status]
+        );
+    }
+
+    #[test]
+    fn unknown_capabilities_are_never_negotiated() {
+        let ours = [Capability::Abort, Capability::Unknown];
+        let theirs = [Capability::Unknown, Capability::Abort];
+
+        assert_eq!(
+            negotiate_capabilities(&ours, &theirs),
+            vec![Capability::Abort]
+        );
+        assert!(negotiate_capabilities(CAPABILITIES, &[]).is_empty());
+    }
+
+    #[test]
+    fn old_limits_have_no_capabilities() {
+        let limits: Limits =
+            serde_json::from_str(r#"{"max_file_size": 100, "max_chunk_size": 10}"#).unwrap();
+
+        assert_eq!(limits.version, 0);
+        assert!(limits.capabilities.is_empty());
+    }
+
+    fn status(size: u64, chunk_size: u64, received: Vec<Range<usize>>) -> FileStatus {
+        FileStatus {
+            size,
+            chunk_size,
+            extension: String::new(),
+            name: String::new(),
+            content_type: String::new(),
+            metadata: HashMap::new(),
+            received,
+            state: UploadState::Uploading,
+        }
+    }
+
+    #[test]
+    #[allow(clippy::single_range_in_vec_init)]
+    fn missing_includes_the_tail_chunk() {
+        assert_eq!(status(10, 4, vec![0..2]).missing(), vec![2..3]);
+        assert_eq!(status(10, 4, vec![]).missing(), vec![0..3]);
+        assert!(status(10, 4, vec![0..3]).missing().is_empty());
+    }
+
+    #[test]
+    fn missing_handles_unsorted_and_overlapping_ranges() {
+        let status = status(20, 2, vec![6..8, 0..2, 1..3, 7..9]);
+
+        assert_eq!(status.missing(), vec![3..6, 9..10]);
+    }
+
+    #[test]
+    fn missing_ignores_ranges_beyond_the_last_chunk() {
+        assert_eq!(status(10, 4, vec![1..2, 5..9]).missing(), vec![0..1, 2..3]);
+    }
+
+    #[test]
+    fn zero_chunk_size_has_no_chunks() {
+        let status = status(10, 0, vec![]);
+
+        assert_eq!(status.chunk_count(), 0);
+        assert!(status.missing().is_empty());
+    }
+}
@@ -1,12 +1,26 @@
+mod cancel;
 mod chunk;
 mod error;
+mod event;
 pub(crate) mod json;
+mod options;
+pub(crate) mod retry;
+mod session;
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) mod throttle;
 mod upload;
 
-pub(crate) use chunk::{Chunk, ChunkIterator};
+pub(crate) use chunk::{Chunk, ChunkIterator, ChunkQueue};
 pub(crate) use upload::Uploader;
 
-pub const FILE_ROUTE: &'static str = "file";
-pub const PART_NAME: &'static str = "chunk";
+pub const FILE_ROUTE: &str = "file";
+pub const PART_NAME: &str = "chunk";
+pub const APPEND_ROUTE: &str = "append";
+pub const LIMITS_ROUTE: &str = "limits";
 
+pub use cancel::CancelToken;
 pub use error::*;
+pub(crate) use event::EventSender;
+pub use event::{DownloadEvent, DownloadEvents, EventStream, UploadEvent, UploadEvents};
+pub use options::{ClientOptions, FileInfo};
+pub use session::{SessionStatus, UploadSession};
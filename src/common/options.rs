@@ -0,0 +1,103 @@
+#[cfg(not(target_arch = "wasm32"))]
+use crate::common::throttle::Throttle;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::ChuaResult;
+use std::collections::HashMap;
+#[cfg(not(target_arch = "wasm32"))]
+use std::sync::Arc;
+use std::time::Duration;
+
+/// HTTP 客户端的配置，上传和下载共用
+#[derive(Debug, Clone)]
+pub struct ClientOptions {
+    /// 单个请求的超时时间
+    pub timeout: Duration,
+
+    /// 跨域请求时是否携带 Cookie 等凭据（`credentials: "include"`）
+    #[cfg(target_arch = "wasm32")]
+    pub credentials: bool,
+
+    /// HTTP 代理，如 `http://proxy.example.com:8080`，为 `None` 时使用系统代理
+    #[cfg(not(target_arch = "wasm32"))]
+    pub proxy: Option<String>,
+
+    /// 额外信任的根证书（PEM 格式），用于私有 CA
+    #[cfg(not(target_arch = "wasm32"))]
+    pub root_certificates: Vec<Vec<u8>>,
+
+    /// 客户端证书链和私钥（PEM 格式），用于双向 TLS
+    #[cfg(not(target_arch = "wasm32"))]
+    pub identity: Option<Vec<u8>>,
+
+    /// 一次上传或下载中所有分片合计的带宽上限（字节/秒），为 `None` 时不限制
+    #[cfg(not(target_arch = "wasm32"))]
+    pub max_bytes_per_second: Option<u64>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Default for ClientOptions {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(20),
+            proxy: None,
+            root_certificates: Vec::new(),
+            identity: None,
+            max_bytes_per_second: None,
+        }
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+impl Default for ClientOptions {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(20),
+            credentials: false,
+        }
+    }
+}
+
+/// 随文件一起提交给服务端的描述信息
+#[derive(Debug, Clone, Default)]
+pub struct FileInfo {
+    /// 原始文件名，为 `None` 时使用本地文件名
+    pub name: Option<String>,
+
+    /// MIME 类型，为 `None` 时自动检测
+    pub content_type: Option<String>,
+
+    /// 自定义的元数据
+    pub metadata: HashMap<String, String>,
+
+    /// 整个文件的 md5，为 `None` 时不提交
+    pub md5: Option<String>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl ClientOptions {
+    pub(crate) fn build_client(&self) -> ChuaResult<reqwest::Client> {
+        use reqwest::{Certificate, Identity, Proxy};
+
+        let mut builder = reqwest::ClientBuilder::new().timeout(self.timeout);
+
+        if let Some(proxy) = &self.proxy {
+            builder = builder.proxy(Proxy::all(proxy.as_str())?);
+        }
+
+        for pem in &self.root_certificates {
+            builder = builder.add_root_certificate(Certificate::from_pem(pem)?);
+        }
+
+        if let Some(pem) = &self.identity {
+            builder = builder.identity(Identity::from_pem(pem)?);
+        }
+
+        Ok(builder.build()?)
+    }
+
+    /// 同一次上传或下载的所有分片共用一个限速器
+    pub(crate) fn build_throttle(&self) -> Option<Arc<Throttle>> {
+        self.max_bytes_per_second
+            .map(|rate| Arc::new(Throttle::new(rate)))
+    }
+}
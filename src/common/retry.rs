@@ -0,0 +1,143 @@
+use crate::{ChuaError, ChuaResult};
+use std::future::Future;
+
+/// 单个分片失败后最多重试的次数
+pub(crate) const MAX_RETRIES: usize = 3;
+
+/// 执行 `f`，遇到临时性错误时最多重试 `MAX_RETRIES` 次，返回最后一次的结果。
+///
+/// 每次重试前以重试次数和上一次的错误调用 `on_retry`。其他错误（如服务端拒绝了分片）重试也不会成功，直接返回。
+pub(crate) async fn retry<F, Fut, T, R>(mut f: F, mut on_retry: R) -> ChuaResult<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = ChuaResult<T>>,
+    R: FnMut(usize, &ChuaError),
+{
+    let mut attempt = 0;
+
+    loop {
+        match f().await {
+            Ok(value) => return Ok(value),
+            Err(e) if attempt >= MAX_RETRIES || !is_transient(&e) => return Err(e),
+            Err(e) => {
+                attempt += 1;
+
+                log::warn!("attempt {} failed: {}, retrying.", attempt, e);
+                on_retry(attempt, &e);
+
+                #[cfg(not(target_arch = "wasm32"))]
+                tokio::time::delay_for(std::time::Duration::from_millis(500 * attempt as u64))
+                    .await;
+            }
+        }
+    }
+}
+
+/// 连接失败、超时、传输中断和 5xx 响应可能下一次就恢复，值得重试
+pub(crate) fn is_transient(e: &ChuaError) -> bool {
+    match e {
+        ChuaError::Http(e) => {
+            e.is_timeout()
+                || e.is_connect()
+                || e.is_request()
+                || e.is_body()
+                || matches!(e.status(), Some(s) if s.is_server_error())
+        }
+        ChuaError::HttpStatus { status, .. } => (500..600).contains(status),
+        #[cfg(target_arch = "wasm32")]
+        ChuaError::Network(_) => true,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{StatusError, UploadChunkError};
+    use std::cell::Cell;
+    use std::net::TcpListener;
+
+    fn status(status: u16) -> ChuaError {
+        ChuaError::HttpStatus {
+            method: "PUT".into(),
+            url: "http://localhost/file".into(),
+            status,
+        }
+    }
+
+    /// 返回 `errors` 中的错误直到用完，之后成功；记录调用次数和重试次数
+    async fn run(errors: Vec<ChuaError>) -> (ChuaResult<()>, usize, usize) {
+        let errors = std::cell::RefCell::new(errors.into_iter());
+        let calls = Cell::new(0);
+        let retries = Cell::new(0);
+
+        let result = retry(
+            || {
+                calls.set(calls.get() + 1);
+                let next = errors.borrow_mut().next();
+                async move { next.map_or(Ok(()), Err) }
+            },
+            |attempt, _| retries.set(attempt),
+        )
+        .await;
+
+        (result, calls.get(), retries.get())
+    }
+
+    #[tokio::test]
+    async fn retries_server_errors() {
+        let (result, calls, retries) = run(vec![status(503), status(502)]).await;
+
+        assert!(result.is_ok());
+        assert_eq!(calls, 3);
+        assert_eq!(retries, 2);
+    }
+
+    #[tokio::test]
+    async fn does_not_retry_rejected_requests() {
+        let errors = vec![
+            status(404),
+            UploadChunkError::Size {
+                expected: 4,
+                actual: 3,
+            }
+            .into(),
+            StatusError::NotFound.into(),
+            "bad response".into(),
+        ];
+
+        for error in errors {
+            let (result, calls, retries) = run(vec![error]).await;
+
+            assert!(result.is_err());
+            assert_eq!(calls, 1);
+            assert_eq!(retries, 0);
+        }
+    }
+
+    #[tokio::test]
+    async fn connection_errors_are_transient() {
+        // 绑定后立即关闭，得到一个没有监听的端口
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+
+        let e = reqwest::get(&format!("http://127.0.0.1:{}/", port))
+            .await
+            .unwrap_err();
+
+        assert!(is_transient(&ChuaError::Http(e)));
+    }
+
+    #[test]
+    fn only_server_errors_are_transient() {
+        assert!(is_transient(&status(500)));
+        assert!(is_transient(&status(503)));
+        assert!(!is_transient(&status(400)));
+        assert!(!is_transient(&status(404)));
+        assert!(!is_transient(&status(413)));
+        assert!(!is_transient(&ChuaError::Aborted));
+    }
+}
@@ -0,0 +1,390 @@
+use crate::common::{CancelToken, Chunk, ClientOptions, EventSender, UploadEvent, Uploader};
+use crate::{
+    negotiate_capabilities, AbortResult, AppendError, AppendParam, AppendResult, Capability,
+    ChuaError, ChuaResult, CompleteResult, FileStatus, InitializeError, InitializeParam,
+    InitializeResult, StatusResult, UploadState, CAPABILITIES, PROTOCOL_VERSION,
+};
+use reqwest::IntoUrl;
+use std::collections::BTreeSet;
+use std::sync::{Arc, Mutex};
+use uuid::Uuid;
+
+/// 调用方没有指定分片大小，且服务端的限制允许时使用的分片大小
+const DEFAULT_CHUNK_SIZE: u64 = 4 * 1024 * 1024;
+
+/// 一次上传的会话。
+///
+/// 初始化、上传分片和完成这几个步骤可以分别调用，分片也可以由不同的机器或进程
+/// 通过 [`UploadSession::attach`] 接续同一个文件ID来上传。克隆出的会话共享同一个
+/// `reqwest::Client`。
+#[derive(Debug, Clone)]
+pub struct UploadSession {
+    uploader: Uploader,
+    id: Uuid,
+    duplicated: bool,
+    chunk_size: u64,
+    version: u32,
+    capabilities: Vec<Capability>,
+    acknowledged: Arc<Mutex<BTreeSet<usize>>>,
+}
+
+/// 会话在本地记录的状态
+#[derive(Debug, Clone)]
+pub struct SessionStatus {
+    /// 文件ID
+    pub id: Uuid,
+
+    /// 是否已上传过
+    pub duplicated: bool,
+
+    /// 经由这个会话（及其克隆）上传成功的分片序号
+    pub acknowledged: Vec<usize>,
+}
+
+impl UploadSession {
+    /// 向服务端发起初始化请求，开始一次新的上传。
+    ///
+    /// `param.chunk_size` 为 0 时根据服务端的限制选择分片大小；服务端不接受分片大小时会按
+    /// 它给出的上限自动调整，实际的分片大小见 [`UploadSession::chunk_size`]。
+    /// `param.content_type` 为空时按扩展名推断。
+    pub async fn initialize(
+        base_url: impl IntoUrl,
+        param: InitializeParam,
+        options: &ClientOptions,
+    ) -> ChuaResult<Self> {
+        let uploader = Uploader::new(base_url, options, EventSender::default()).await?;
+
+        Self::start(uploader, param).await
+    }
+
+    /// 接续一个已经初始化过的上传，服务端支持的能力通过 `GET /limits` 查询
+    pub async fn attach(
+        base_url: impl IntoUrl,
+        id: Uuid,
+        options: &ClientOptions,
+    ) -> ChuaResult<Self> {
+        let uploader = Uploader::new(base_url, options, EventSender::default()).await?;
+
+        let mut session = Self::new(uploader, id, false, 0);
+        session.discover_capabilities().await;
+
+        Ok(session)
+    }
+
+    pub(crate) async fn start(uploader: Uploader, mut param: InitializeParam) -> ChuaResult<Self> {
+        if param.chunk_size == 0 {
+            param.chunk_size = choose_chunk_size(&uploader, param.size).await?;
+        }
+
+        if param.content_type.is_empty() {
+            param.content_type = mime_guess::from_ext(&param.extension)
+                .first_or_octet_stream()
+                .to_string();
+        }
+
+        let mut adjusted = false;
+
+        loop {
+            match uploader.initialize(&param).await? {
+                InitializeResult::Ok {
+                    id,
+                    duplicated,
+                    version,
+                    capabilities,
+                } => {
+                    let mut session = Self::new(uploader, id, duplicated, param.chunk_size);
+                    session.version = version;
+                    session.capabilities = capabilities;
+                    return Ok(session);
+                }
+                // 分片大小不被接受时，按服务端给出的上限重新初始化一次
+                InitializeResult::Err {
+                    error: InitializeError::ChunkSize { max },
+                } if !adjusted && max > 0 && max != param.chunk_size => {
+                    log::info!(
+                        "chunk size {} rejected, retrying with {}.",
+                        param.chunk_size,
+                        max
+                    );
+                    param.chunk_size = max;
+                    adjusted = true;
+                }
+                InitializeResult::Err { error } => return Err(error.into()),
+            }
+        }
+    }
+
+    pub(crate) async fn start_append(
+        uploader: Uploader,
+        id: Uuid,
+        mut param: AppendParam,
+    ) -> ChuaResult<Self> {
+        let mut session = Self::new(uploader, id, false, param.chunk_size);
+        session.discover_capabilities().await;
+
+        if !session.supports(Capability::Append) {
+            return Err(ChuaError::Unsupported(Capability::Append));
+        }
+
+        if param.chunk_size == 0 {
+            param.chunk_size =
+                choose_chunk_size(&session.uploader, param.offset + param.size).await?;
+        }
+
+        let mut adjusted = false;
+
+        loop {
+            match session.uploader.append(&id, &param).await? {
+                AppendResult::Ok => {
+                    session.chunk_size = param.chunk_size;
+                    return Ok(session);
+                }
+                // 同初始化，分片大小不被接受时按服务端给出的上限重试一次
+                AppendResult::Err {
+                    error: AppendError::ChunkSize { max },
+                } if !adjusted && max > 0 && max != param.chunk_size => {
+                    log::info!(
+                        "chunk size {} rejected, retrying with {}.",
+                        param.chunk_size,
+                        max
+                    );
+                    param.chunk_size = max;
+                    adjusted = true;
+                }
+                AppendResult::Err { error } => return Err(error.into()),
+            }
+        }
+    }
+
+    /// 接续服务端上已有的上传，沿用服务端记录的分片大小，已收到的分片记为已确认。
+    /// `size` 是本地文件的大小，与服务端记录的不一致时返回错误
+    pub(crate) async fn resume(
+        uploader: Uploader,
+        id: Uuid,
+        size: u64,
+    ) -> ChuaResult<(Self, FileStatus)> {
+        let mut session = Self::new(uploader, id, false, 0);
+        session.discover_capabilities().await;
+
+        let status = session.server_status().await?;
+
+        if status.size != size {
+            return Err(ChuaError::SizeMismatch {
+                local: size,
+                remote: status.size,
+            });
+        }
+
+        if status.state == UploadState::Completing {
+            return Err("The upload is being completed".into());
+        }
+
+        session.chunk_size = status.chunk_size;
+
+        let mut received_size = 0;
+        let mut received_chunks = 0;
+
+        for index in status.received.iter().cloned().flatten() {
+            let start = index as u64 * status.chunk_size;
+            received_size += status.size.saturating_sub(start).min(status.chunk_size);
+            received_chunks += 1;
+            session.acknowledge(index);
+        }
+
+        session.uploader.emit(UploadEvent::Resumed {
+            id,
+            size: status.size,
+            chunk_size: status.chunk_size,
+            received_size,
+            received_chunks,
+        });
+
+        Ok((session, status))
+    }
+
+    fn new(uploader: Uploader, id: Uuid, duplicated: bool, chunk_size: u64) -> Self {
+        Self {
+            uploader,
+            id,
+            duplicated,
+            chunk_size,
+            version: 0,
+            capabilities: Vec::new(),
+            acknowledged: Default::default(),
+        }
+    }
+
+    /// 文件ID
+    pub fn id(&self) -> Uuid {
+        self.id
+    }
+
+    /// 服务端是否已有这个文件，为 `true` 时无需再上传分片
+    pub fn duplicated(&self) -> bool {
+        self.duplicated
+    }
+
+    /// 实际使用的分片大小，可能已按服务端的限制调整过；未经初始化时为 0
+    pub fn chunk_size(&self) -> u64 {
+        self.chunk_size
+    }
+
+    /// 初始化时双方商定的协议版本，未经初始化（如 [`UploadSession::attach`]）时为 0
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    /// 初始化时双方商定的能力
+    pub fn capabilities(&self) -> &[Capability] {
+        &self.capabilities
+    }
+
+    /// 服务端是否支持某项能力
+    pub fn supports(&self, capability: Capability) -> bool {
+        self.capabilities.contains(&capability)
+    }
+
+    /// 上传第 `index` 个分片，失败时会自动重试
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn upload_chunk(&self, index: usize, data: Vec<u8>) -> ChuaResult<()> {
+        let size = data.len() as u64;
+
+        self.uploader
+            .upload_chunk(self.id, &Chunk { index, data }, size)
+            .await?;
+
+        self.acknowledge(index);
+
+        Ok(())
+    }
+
+    /// 上传第 `index` 个分片，失败时会自动重试
+    #[cfg(target_arch = "wasm32")]
+    pub async fn upload_chunk(&self, index: usize, data: web_sys::Blob) -> ChuaResult<()> {
+        let size = data.size() as u64;
+
+        self.uploader
+            .upload_chunk(self.id, &Chunk { index, data }, size)
+            .await?;
+
+        self.acknowledge(index);
+
+        Ok(())
+    }
+
+    /// 没有经过初始化的会话通过 `GET /limits` 得知服务端的版本和能力，旧的服务端视为都不支持
+    async fn discover_capabilities(&mut self) {
+        match self.uploader.limits().await {
+            Ok(limits) => {
+                self.version = limits.version.min(PROTOCOL_VERSION);
+                self.capabilities = negotiate_capabilities(CAPABILITIES, &limits.capabilities);
+            }
+            Err(e) => log::debug!("cannot get limits: {}, assuming no capabilities.", e),
+        }
+    }
+
+    fn acknowledge(&self, index: usize) {
+        if let Ok(mut acknowledged) = self.acknowledged.lock() {
+            acknowledged.insert(index);
+        }
+    }
+
+    /// 放弃上传，由服务端删除已上传的分片。服务端不支持时返回 [`ChuaError::Unsupported`]
+    pub async fn abort(&self) -> ChuaResult<()> {
+        if !self.supports(Capability::Abort) {
+            return Err(ChuaError::Unsupported(Capability::Abort));
+        }
+
+        match self.uploader.abort(&self.id).await? {
+            AbortResult::Ok => Ok(()),
+            AbortResult::Err { error } => Err(error.into()),
+        }
+    }
+
+    pub(crate) fn cancel_token(&self) -> &CancelToken {
+        self.uploader.cancel_token()
+    }
+
+    /// 查询服务端保存的文件信息、已收到的分片和上传状态，没有副作用。
+    /// 服务端不支持时返回 [`ChuaError::Unsupported`]
+    pub async fn server_status(&self) -> ChuaResult<FileStatus> {
+        if !self.supports(Capability::Status) {
+            return Err(ChuaError::Unsupported(Capability::Status));
+        }
+
+        match self.uploader.status(&self.id).await? {
+            StatusResult::Ok(status) => Ok(status),
+            StatusResult::Err { error } => Err(error.into()),
+        }
+    }
+
+    /// 会话在本地记录的状态
+    pub fn status(&self) -> SessionStatus {
+        let acknowledged = match self.acknowledged.lock() {
+            Ok(acknowledged) => acknowledged.iter().copied().collect(),
+            Err(_) => Vec::new(),
+        };
+
+        SessionStatus {
+            id: self.id,
+            duplicated: self.duplicated,
+            acknowledged,
+        }
+    }
+
+    /// 通知服务端所有分片已上传，由服务端合并文件
+    pub async fn complete(&self) -> ChuaResult<()> {
+        match self.uploader.complete(&self.id).await? {
+            CompleteResult::Ok => Ok(()),
+            CompleteResult::Err { error } => Err(error.into()),
+        }
+    }
+}
+
+/// 调用方没有指定分片大小时，根据服务端的限制选择一个
+async fn choose_chunk_size(uploader: &Uploader, size: u64) -> ChuaResult<u64> {
+    match uploader.limits().await {
+        Ok(limits) => {
+            if size > limits.max_file_size {
+                return Err(InitializeError::Size {
+                    max: limits.max_file_size,
+                }
+                .into());
+            }
+
+            Ok(DEFAULT_CHUNK_SIZE.min(limits.max_chunk_size).max(1))
+        }
+        // 旧的服务端没有这个接口，先用默认值，不合适时再按服务端的回复调整
+        Err(e) => {
+            log::debug!("cannot get limits: {}, using the default chunk size.", e);
+            Ok(DEFAULT_CHUNK_SIZE)
+        }
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn unsupported_requests_are_not_sent() {
+        // 没有服务在监听，请求一旦发出就会以网络错误失败
+        let uploader = Uploader::new(
+            "http://127.0.0.1:9/",
+            &ClientOptions::default(),
+            EventSender::default(),
+        )
+        .await
+        .unwrap();
+        let session = UploadSession::new(uploader, Uuid::nil(), false, 0);
+
+        assert!(matches!(
+            session.server_status().await,
+            Err(ChuaError::Unsupported(Capability::Status))
+        ));
+        assert!(matches!(
+            session.abort().await,
+            Err(ChuaError::Unsupported(Capability::Abort))
+        ));
+    }
+}
@@ -0,0 +1,68 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::time::delay_until;
+
+/// 限制多个任务的总带宽：每次发送前登记字节数，按速率排到前一次之后再开始
+#[derive(Debug)]
+pub(crate) struct Throttle {
+    bytes_per_second: u64,
+    next: Mutex<Instant>,
+}
+
+impl Throttle {
+    pub(crate) fn new(bytes_per_second: u64) -> Self {
+        Self {
+            bytes_per_second: bytes_per_second.max(1),
+            next: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// 等到可以传输 `bytes` 字节的时刻
+    pub(crate) async fn acquire(&self, bytes: u64) {
+        let start = match self.next.lock() {
+            Ok(mut next) => {
+                let start = (*next).max(Instant::now());
+                *next = start + self.duration(bytes);
+                start
+            }
+            Err(_) => return,
+        };
+
+        delay_until(start.into()).await;
+    }
+
+    fn duration(&self, bytes: u64) -> Duration {
+        let nanos = bytes as u128 * 1_000_000_000 / self.bytes_per_second as u128;
+
+        Duration::from_nanos(nanos.min(u64::MAX as u128) as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn spaces_out_transfers() {
+        let throttle = Throttle::new(1000);
+        let begin = Instant::now();
+
+        // 第一次立即开始，之后每次排在前一次的 100 字节之后
+        for _ in 0..3 {
+            throttle.acquire(100).await;
+        }
+
+        let elapsed = begin.elapsed();
+        assert!(elapsed >= Duration::from_millis(200), "{:?}", elapsed);
+        assert!(elapsed < Duration::from_millis(1000), "{:?}", elapsed);
+    }
+
+    #[test]
+    fn duration_is_proportional_to_bytes() {
+        let throttle = Throttle::new(4096);
+
+        assert_eq!(throttle.duration(4096), Duration::from_secs(1));
+        assert_eq!(throttle.duration(1024), Duration::from_millis(250));
+        assert_eq!(Throttle::new(0).duration(1), Duration::from_secs(1));
+    }
+}
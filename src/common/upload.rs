@@ -1,125 +1,288 @@
-use crate::common::{ChuaError, Chunk, FILE_ROUTE, PART_NAME};
-use crate::{ChuaResult, CompleteResult, InitializeParam, InitializeResult};
-use futures::SinkExt;
-use futures_channel::{mpsc, oneshot};
-use reqwest::{IntoUrl, Url};
-use uuid::Uuid;
-
+use crate::common::retry::retry;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::common::throttle::Throttle;
+use crate::common::{
+    CancelToken, Chunk, ClientOptions, EventSender, UploadEvent, APPEND_ROUTE, FILE_ROUTE,
+    LIMITS_ROUTE, PART_NAME,
+};
+use crate::{
+    AbortResult, AppendParam, AppendResult, ChuaError, ChuaResult, CompleteResult, InitializeParam,
+    InitializeResult, Limits, StatusResult, UploadChunkResult,
+};
+use reqwest::{IntoUrl, Method, Url};
+use serde::de::DeserializeOwned;
 #[cfg(not(target_arch = "wasm32"))]
-use std::time::Duration;
+use std::sync::Arc;
+use uuid::Uuid;
 
 #[derive(Debug, Clone)]
 pub(crate) struct Uploader {
+    #[cfg(not(target_arch = "wasm32"))]
     client: reqwest::Client,
+    #[cfg(not(target_arch = "wasm32"))]
+    throttle: Option<Arc<Throttle>>,
+    #[cfg(target_arch = "wasm32")]
+    options: ClientOptions,
     base_url: Url,
+    events: EventSender,
+    cancel: CancelToken,
 }
 
 impl Uploader {
-    #[cfg(target_arch = "wasm32")]
-    pub(crate) async fn new(base_url: impl IntoUrl) -> Result<Self, ChuaError> {
+    pub(crate) async fn new(
+        base_url: impl IntoUrl,
+        options: &ClientOptions,
+        events: EventSender,
+    ) -> ChuaResult<Self> {
         Ok(Self {
-            client: reqwest::Client::new(),
+            #[cfg(not(target_arch = "wasm32"))]
+            client: options.build_client()?,
+            #[cfg(not(target_arch = "wasm32"))]
+            throttle: options.build_throttle(),
+            #[cfg(target_arch = "wasm32")]
+            options: options.clone(),
             base_url: base_url.into_url()?,
+            events,
+            cancel: CancelToken::new(),
         })
     }
-    #[cfg(not(target_arch = "wasm32"))]
-    pub(crate) async fn new(base_url: impl IntoUrl, timeout: Duration) -> ChuaResult<Self> {
-        Ok(Self {
-            client: reqwest::ClientBuilder::new().timeout(timeout).build()?,
-            base_url: base_url.into_url()?,
-        })
+
+    /// 使用调用方的取消令牌
+    pub(crate) fn with_cancel(mut self, cancel: CancelToken) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    pub(crate) fn cancel_token(&self) -> &CancelToken {
+        &self.cancel
+    }
+
+    pub(crate) fn emit(&self, event: UploadEvent) {
+        self.events.emit(event);
+    }
+
+    pub(crate) async fn limits(&self) -> ChuaResult<Limits> {
+        let url = self.base_url.join(LIMITS_ROUTE)?;
+
+        self.request(Method::GET, url, None).await
     }
 
-    pub(crate) async fn initialize(&self, param: InitializeParam) -> ChuaResult<InitializeResult> {
-        let url = self.base_url.join(&format!("{}", FILE_ROUTE))?;
+    pub(crate) async fn initialize(&self, param: &InitializeParam) -> ChuaResult<InitializeResult> {
+        let url = self.base_url.join(FILE_ROUTE)?;
 
         let result: InitializeResult = self
-            .client
-            .post(url)
-            .body(serde_json::to_string(&param)?)
-            .send()
-            .await?
-            .json()
+            .request(Method::POST, url, Some(serde_json::to_string(param)?))
             .await?;
 
+        if let InitializeResult::Ok { id, duplicated, .. } = &result {
+            self.events.emit(UploadEvent::Initialized {
+                id: *id,
+                duplicated: *duplicated,
+                size: param.size,
+                chunk_size: param.chunk_size,
+            });
+        }
+
         Ok(result)
     }
 
-    pub(crate) async fn complete(self, file_id: &Uuid) -> ChuaResult<CompleteResult> {
+    pub(crate) async fn append(
+        &self,
+        file_id: &Uuid,
+        param: &AppendParam,
+    ) -> ChuaResult<AppendResult> {
+        let url = self
+            .base_url
+            .join(&format!("{}/{}/{}", FILE_ROUTE, file_id, APPEND_ROUTE))?;
+
+        let result: AppendResult = self
+            .request(Method::POST, url, Some(serde_json::to_string(param)?))
+            .await?;
+
+        if let AppendResult::Ok = &result {
+            self.events.emit(UploadEvent::Initialized {
+                id: *file_id,
+                duplicated: false,
+                size: param.size,
+                chunk_size: param.chunk_size,
+            });
+        }
+
+        Ok(result)
+    }
+
+    pub(crate) async fn complete(&self, file_id: &Uuid) -> ChuaResult<CompleteResult> {
         let url = self.base_url.join(&format!("{}/{}", FILE_ROUTE, file_id))?;
 
-        let result: CompleteResult = self.client.post(url).send().await?.json().await?;
+        self.events.emit(UploadEvent::Completing);
+
+        let result: CompleteResult = self.request(Method::POST, url, None).await?;
+
+        if let CompleteResult::Ok = result {
+            self.events.emit(UploadEvent::Completed { id: *file_id });
+        }
 
         Ok(result)
     }
 
+    pub(crate) async fn abort(&self, file_id: &Uuid) -> ChuaResult<AbortResult> {
+        let url = self.base_url.join(&format!("{}/{}", FILE_ROUTE, file_id))?;
+
+        self.request(Method::DELETE, url, None).await
+    }
+
+    pub(crate) async fn status(&self, file_id: &Uuid) -> ChuaResult<StatusResult> {
+        let url = self.base_url.join(&format!("{}/{}", FILE_ROUTE, file_id))?;
+
+        self.request(Method::GET, url, None).await
+    }
+
+    /// 发送 JSON 请求并解析响应，状态码不是 2xx 时返回错误
     #[cfg(not(target_arch = "wasm32"))]
-    pub(crate) async fn upload_chunk(
-        self,
-        file_id: Uuid,
-        mut sender: mpsc::UnboundedSender<oneshot::Sender<Option<Chunk<Vec<u8>>>>>,
-    ) -> Result<(), ChuaError> {
-        loop {
-            let (os, or) = oneshot::channel();
-
-            sender.send(os).await?;
-
-            match or.await? {
-                None => break,
-                Some(chunk) => {
-                    let len = chunk.data.len();
-                    let index = chunk.index;
-
-                    let resp = self.send_chunk(file_id, chunk).await?;
-
-                    println!(
-                        "{}.part{:?} ({} bytes) uploaded, response: {}.",
-                        file_id, index, len, resp
-                    );
-                }
-            }
+    async fn request<T: DeserializeOwned>(
+        &self,
+        method: Method,
+        url: Url,
+        body: Option<String>,
+    ) -> ChuaResult<T> {
+        use reqwest::header::CONTENT_TYPE;
+
+        let mut builder = self.client.request(method.clone(), url.clone());
+
+        if let Some(body) = body {
+            builder = builder.header(CONTENT_TYPE, "application/json").body(body);
+        }
+
+        let resp = builder.send().await?;
+
+        if !resp.status().is_success() {
+            return Err(ChuaError::HttpStatus {
+                method: method.to_string(),
+                url: url.to_string(),
+                status: resp.status().as_u16(),
+            });
         }
 
-        Ok(())
+        Ok(resp.json().await?)
     }
 
-    // 用这个针对 wasm 单独实现的 multipart上传，就可以工作了；
-    // 使用reqwest实现的send_chunk在native和wasm下都能编译，但是在 wasm下有bug，有明显卡顿且上传的分片不正确）
+    /// 发送 JSON 请求并解析响应，状态码不是 2xx 时返回错误
     #[cfg(target_arch = "wasm32")]
+    async fn request<T: DeserializeOwned>(
+        &self,
+        method: Method,
+        url: Url,
+        body: Option<String>,
+    ) -> ChuaResult<T> {
+        use crate::wasm::fetch::fetch;
+        use wasm_bindgen::JsValue;
+
+        let (body, content_type) = match body {
+            Some(body) => (Some(JsValue::from(body)), Some("application/json")),
+            None => (None, None),
+        };
+
+        let bytes = fetch(
+            method.as_str(),
+            &url,
+            body.as_ref(),
+            content_type,
+            &self.options,
+        )
+        .await?;
+
+        Ok(serde_json::from_slice(&bytes)?)
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
     pub(crate) async fn upload_chunk(
-        self,
+        &self,
         file_id: Uuid,
-        mut sender: mpsc::UnboundedSender<oneshot::Sender<Option<Chunk<web_sys::Blob>>>>,
+        chunk: &Chunk<Vec<u8>>,
+        size: u64,
     ) -> ChuaResult<()> {
-        loop {
-            let (os, or) = oneshot::channel();
-
-            sender.send(os).await?;
-
-            match or.await? {
-                None => break,
-                Some(chunk) => {
-                    let index = chunk.index;
+        let index = chunk.index;
+
+        self.events.emit(UploadEvent::ChunkStarted { index, size });
+
+        let result = retry(
+            || self.send_chunk(file_id, chunk),
+            |attempt, e| {
+                self.events.emit(UploadEvent::ChunkRetried {
+                    index,
+                    attempt,
+                    error: e.to_string(),
+                })
+            },
+        )
+        .await;
+
+        self.acknowledge(file_id, index, size, result)
+    }
 
-                    let resp = self.send_chunk(file_id, chunk).await?;
+    #[cfg(target_arch = "wasm32")]
+    pub(crate) async fn upload_chunk(
+        &self,
+        file_id: Uuid,
+        chunk: &Chunk<web_sys::Blob>,
+        size: u64,
+    ) -> ChuaResult<()> {
+        let index = chunk.index;
+
+        self.events.emit(UploadEvent::ChunkStarted { index, size });
+
+        let result = retry(
+            || self.send_chunk(file_id, chunk),
+            |attempt, e| {
+                self.events.emit(UploadEvent::ChunkRetried {
+                    index,
+                    attempt,
+                    error: e.to_string(),
+                })
+            },
+        )
+        .await;
+
+        self.acknowledge(file_id, index, size, result)
+    }
 
-                    println!("{}.part{:?} uploaded, response: {}.", file_id, index, resp);
-                }
+    fn acknowledge(
+        &self,
+        file_id: Uuid,
+        index: usize,
+        size: u64,
+        result: ChuaResult<()>,
+    ) -> ChuaResult<()> {
+        match result {
+            Ok(()) => {
+                log::debug!("{}.part{} ({} bytes) uploaded.", file_id, index, size);
+                self.events
+                    .emit(UploadEvent::ChunkAcknowledged { index, size });
+                Ok(())
+            }
+            Err(e) => {
+                self.events.emit(UploadEvent::ChunkFailed {
+                    index,
+                    error: e.to_string(),
+                });
+                Err(e)
             }
         }
-
-        Ok(())
     }
 
     // TODO: 这段代码在 wasm32 下不能工作，考虑为 wasm32 单独实现
     #[cfg(not(target_arch = "wasm32"))]
-    async fn send_chunk(&self, file_id: Uuid, chunk: Chunk<Vec<u8>>) -> ChuaResult<String> {
+    async fn send_chunk(&self, file_id: Uuid, chunk: &Chunk<Vec<u8>>) -> ChuaResult<()> {
         use reqwest::multipart::*;
 
         let Chunk { index, data } = chunk;
 
+        if let Some(throttle) = &self.throttle {
+            throttle.acquire(data.len() as u64).await;
+        }
+
         let file_id = file_id.to_string();
-        let file = Part::bytes(data).file_name(file_id.clone());
+        let file = Part::bytes(data.clone()).file_name(file_id.clone());
         let form = Form::new().part(PART_NAME, file);
 
         let url = self
@@ -127,61 +290,44 @@ impl Uploader {
             .clone()
             .join(&format!("{}/{}/{}", FILE_ROUTE, file_id, index))?;
 
-        let req = self.client.put(url).multipart(form).send().await?;
+        let req = self.client.put(url.clone()).multipart(form).send().await?;
+
+        if !req.status().is_success() {
+            return Err(ChuaError::HttpStatus {
+                method: "PUT".into(),
+                url: url.to_string(),
+                status: req.status().as_u16(),
+            });
+        }
 
-        Ok(req.text().await?)
+        match req.json().await? {
+            UploadChunkResult::Ok => Ok(()),
+            UploadChunkResult::Err { error } => Err(error.into()),
+        }
     }
 
+    // 用这个针对 wasm 单独实现的 multipart上传，就可以工作了；
+    // 使用reqwest实现的send_chunk在native和wasm下都能编译，但是在 wasm下有bug，有明显卡顿且上传的分片不正确）
     #[cfg(target_arch = "wasm32")]
-    async fn send_chunk(&self, file_id: Uuid, chunk: Chunk<web_sys::Blob>) -> ChuaResult<String> {
-        use crate::wasm::runtime::promise;
-        use js_sys::Uint8Array;
-        use wasm_bindgen::JsValue;
-        use wasm_bindgen::UnwrapThrowExt;
-        use web_sys::{window, FormData, Request, RequestInit, Response};
+    async fn send_chunk(&self, file_id: Uuid, chunk: &Chunk<web_sys::Blob>) -> ChuaResult<()> {
+        use crate::wasm::fetch::{fetch, js_error};
+        use web_sys::FormData;
 
         let Chunk { index, data } = chunk;
 
-        let form = FormData::new().unwrap_throw();
-
-        let js_value: &JsValue = form.as_ref();
-
-        form.append_with_blob(PART_NAME, &data).unwrap_throw();
-
-        let mut init = RequestInit::new();
-
-        init.method("PUT");
+        let form = FormData::new().map_err(js_error)?;
+        form.append_with_blob(PART_NAME, data).map_err(js_error)?;
 
-        init.body(Some(js_value));
-
-        let upload_url = format!("/{}/{}/{}", FILE_ROUTE, file_id, index);
-
-        let js_req = match Request::new_with_str_and_init(&upload_url, &init) {
-            Ok(js_req) => js_req,
-            Err(e) => return Err(format!("{:?}", e).into()),
-        };
-
-        // Await the fetch() promise
-        let p = window()
-            .expect("window should exist")
-            .fetch_with_request(&js_req);
-
-        let js_resp = promise::<Response>(p).await.unwrap_throw();
+        let url = self
+            .base_url
+            .join(&format!("{}/{}/{}", FILE_ROUTE, file_id, index))?;
 
-        let status = js_resp.status();
+        // multipart 的 Content-Type（含 boundary）由浏览器设置
+        let bytes = fetch("PUT", &url, Some(form.as_ref()), None, &self.options).await?;
 
-        if status != 200 {
-            return Err("status code is not 200".into());
+        match serde_json::from_slice(&bytes)? {
+            UploadChunkResult::Ok => Ok(()),
+            UploadChunkResult::Err { error } => Err(error.into()),
         }
-
-        let buf_js = promise::<JsValue>(js_resp.array_buffer().unwrap_throw())
-            .await
-            .unwrap_throw();
-
-        let buffer = Uint8Array::new(&buf_js);
-        let mut bytes = vec![0u8; buffer.length() as usize];
-        buffer.copy_to(&mut bytes);
-
-        Ok(String::from_utf8(bytes)?)
     }
 }
@@ -15,15 +15,29 @@ macro_rules! if_native {
 }
 
 pub use common::json::*;
-pub use common::{ChuaError, ChuaResult};
-pub use common::{FILE_ROUTE, PART_NAME};
+pub use common::{CancelToken, ChuaError, ChuaResult, ClientOptions, FileInfo};
+pub use common::{DownloadEvent, DownloadEvents, EventStream, UploadEvent, UploadEvents};
+pub use common::{SessionStatus, UploadSession};
+pub use common::{APPEND_ROUTE, FILE_ROUTE, LIMITS_ROUTE, PART_NAME};
 
 if_native! {
     mod native;
-    pub use native::upload;
+    pub use native::{
+        append, download, download_with_events, resume, upload, upload_cancelable, upload_file, upload_range,
+        upload_with_events, upload_with_info, upload_with_options,
+    };
+
+    #[cfg(feature = "preprocess")]
+    pub use native::{upload_image, ImageOptions};
+
+    #[cfg(feature = "blocking")]
+    pub mod blocking;
 }
 
 if_wasm! {
     mod wasm;
-    pub use wasm::upload;
+    pub use wasm::{
+        resume_cancelable, upload, upload_cancelable, upload_with_events, upload_with_info,
+        upload_with_options,
+    };
 }
@@ -0,0 +1,396 @@
+use super::file::FileWriter;
+use crate::common::retry::retry;
+use crate::common::throttle::Throttle;
+use crate::common::{ChunkIterator, ClientOptions, EventSender};
+use crate::{ChuaError, ChuaResult, DownloadEvent, DownloadEvents};
+use futures::lock::Mutex;
+use futures::{Future, TryStreamExt};
+use reqwest::header::{ACCEPT_RANGES, CONTENT_LENGTH, RANGE};
+use reqwest::{IntoUrl, StatusCode, Url};
+use std::collections::HashSet;
+use std::ffi::OsString;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::fs::{remove_file, File, OpenOptions};
+use tokio::prelude::*;
+
+/// 断点续传记录文件的后缀
+const PROGRESS_SUFFIX: &str = ".chua";
+
+/// 用 HTTP Range 请求并行下载 `url` 到 `path`，返回文件大小。
+///
+/// 下载进度记录在 `{path}.chua` 中，中断后以相同的参数再次调用会跳过已完成的分片；
+/// 给出 `md5` 时下载完成后会校验整个文件，不一致时删除文件并返回 [`ChuaError::Md5Mismatch`]。
+pub async fn download(
+    url: impl IntoUrl,
+    path: impl AsRef<Path>,
+    chunk_size: u64,
+    parallel: usize,
+    md5: Option<&str>,
+    options: &ClientOptions,
+) -> ChuaResult<u64> {
+    Downloader::new(url, options, EventSender::default())?
+        .run(path.as_ref(), chunk_size, parallel, md5)
+        .await
+}
+
+/// 与 [`download`] 相同，同时返回下载过程的事件流
+pub fn download_with_events(
+    url: impl IntoUrl,
+    path: impl AsRef<Path>,
+    chunk_size: u64,
+    parallel: usize,
+    md5: Option<String>,
+    options: ClientOptions,
+) -> (impl Future<Output = ChuaResult<u64>>, DownloadEvents) {
+    let (events, stream) = EventSender::channel();
+
+    let future = async move {
+        Downloader::new(url, &options, events)?
+            .run(path.as_ref(), chunk_size, parallel, md5.as_deref())
+            .await
+    };
+
+    (future, stream)
+}
+
+struct Downloader {
+    client: reqwest::Client,
+    throttle: Option<Arc<Throttle>>,
+    url: Url,
+    events: EventSender<DownloadEvent>,
+}
+
+impl Downloader {
+    fn new(
+        url: impl IntoUrl,
+        options: &ClientOptions,
+        events: EventSender<DownloadEvent>,
+    ) -> ChuaResult<Self> {
+        Ok(Self {
+            client: options.build_client()?,
+            throttle: options.build_throttle(),
+            url: url.into_url()?,
+            events,
+        })
+    }
+
+    async fn run(
+        &self,
+        path: &Path,
+        chunk_size: u64,
+        parallel: usize,
+        md5: Option<&str>,
+    ) -> ChuaResult<u64> {
+        if chunk_size == 0 {
+            return Err("Chunk size must be greater than 0".into());
+        }
+
+        let size = self.size().await?;
+
+        let progress = Progress::open(path, size, chunk_size).await?;
+
+        let pending = pending(size, chunk_size, &progress.done);
+
+        self.events.emit(DownloadEvent::Started {
+            size,
+            chunk_size,
+            downloaded_size: size - pending.iter().map(|(_, r)| r.end - r.start).sum::<u64>(),
+        });
+
+        // 所有分片共用同一个文件句柄，按位置写入
+        let writer = FileWriter::open(path).await?;
+
+        let parallel = if parallel == 0 {
+            num_cpus::get()
+        } else {
+            parallel
+        };
+
+        futures::stream::iter(pending.into_iter().map(Ok))
+            .try_for_each_concurrent(parallel, |(index, range)| {
+                let writer = &writer;
+                let progress = &progress;
+                async move {
+                    let data = retry(
+                        || self.fetch(range.clone()),
+                        |attempt, e| {
+                            self.events.emit(DownloadEvent::ChunkRetried {
+                                index,
+                                attempt,
+                                error: e.to_string(),
+                            })
+                        },
+                    )
+                    .await?;
+
+                    let size = data.len() as u64;
+                    writer.write(range.start, data).await?;
+                    progress.record(index).await?;
+
+                    self.events
+                        .emit(DownloadEvent::ChunkDownloaded { index, size });
+
+                    Ok::<_, ChuaError>(())
+                }
+            })
+            .await?;
+
+        if let Some(expected) = md5 {
+            self.events.emit(DownloadEvent::Verifying);
+
+            let actual = file_md5(path).await?;
+
+            if !actual.eq_ignore_ascii_case(expected) {
+                // 所有分片都已记录为完成，留着进度也无法修复，删掉后再次下载会从头开始
+                remove_file(path).await?;
+                remove_file(&progress.path).await?;
+
+                return Err(ChuaError::Md5Mismatch {
+                    expected: expected.to_string(),
+                    actual,
+                });
+            }
+        }
+
+        remove_file(&progress.path).await?;
+
+        self.events.emit(DownloadEvent::Completed { size });
+
+        Ok(size)
+    }
+
+    async fn size(&self) -> ChuaResult<u64> {
+        let resp = self.client.head(self.url.clone()).send().await?;
+
+        if !resp.status().is_success() {
+            return Err(ChuaError::HttpStatus {
+                method: "HEAD".into(),
+                url: self.url.to_string(),
+                status: resp.status().as_u16(),
+            });
+        }
+
+        let headers = resp.headers();
+
+        match headers.get(ACCEPT_RANGES) {
+            Some(value) if value == "bytes" => {}
+            _ => return Err("The server does not support range requests".into()),
+        }
+
+        headers
+            .get(CONTENT_LENGTH)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse().ok())
+            .ok_or_else(|| "The server did not report the file size".into())
+    }
+
+    async fn fetch(&self, range: Range<u64>) -> ChuaResult<Vec<u8>> {
+        if let Some(throttle) = &self.throttle {
+            throttle.acquire(range.end - range.start).await;
+        }
+
+        let resp = self
+            .client
+            .get(self.url.clone())
+            .header(RANGE, format!("bytes={}-{}", range.start, range.end - 1))
+            .send()
+            .await?;
+
+        if resp.status() != StatusCode::PARTIAL_CONTENT {
+            return Err(ChuaError::HttpStatus {
+                method: "GET".into(),
+                url: self.url.to_string(),
+                status: resp.status().as_u16(),
+            });
+        }
+
+        let data = resp.bytes().await?;
+
+        let expected = range.end - range.start;
+        if data.len() as u64 != expected {
+            return Err(format!(
+                "Range {:?} should be {} bytes, but got {}",
+                range,
+                expected,
+                data.len()
+            )
+            .into());
+        }
+
+        Ok(data.to_vec())
+    }
+}
+
+/// 还没有下载的分片
+fn pending(size: u64, chunk_size: u64, done: &HashSet<usize>) -> Vec<(usize, Range<u64>)> {
+    ChunkIterator::new(size, chunk_size)
+        .filter(|(index, _)| !done.contains(index))
+        .collect()
+}
+
+/// 下载进度：第一行是 `{size} {chunk_size}`，之后每行是一个已写入的分片序号
+struct Progress {
+    path: PathBuf,
+    file: Mutex<File>,
+    done: HashSet<usize>,
+}
+
+impl Progress {
+    async fn open(target: &Path, size: u64, chunk_size: u64) -> ChuaResult<Self> {
+        let path = {
+            let mut p = OsString::from(target.as_os_str());
+            p.push(PROGRESS_SUFFIX);
+            PathBuf::from(p)
+        };
+
+        let header = format!("{} {}", size, chunk_size);
+
+        let done = match Self::load(&path, &header).await {
+            Some(done) if target.is_file() => done,
+            _ => {
+                // 没有可用的进度，从头开始
+                let mut file = File::create(target).await?;
+                file.set_len(size).await?;
+
+                let mut progress = File::create(&path).await?;
+                progress
+                    .write_all(format!("{}\n", header).as_bytes())
+                    .await?;
+                progress.sync_data().await?;
+
+                HashSet::new()
+            }
+        };
+
+        let file = OpenOptions::new().append(true).open(&path).await?;
+
+        Ok(Self {
+            path,
+            file: Mutex::new(file),
+            done,
+        })
+    }
+
+    async fn load(path: &Path, header: &str) -> Option<HashSet<usize>> {
+        let mut content = String::new();
+        File::open(path)
+            .await
+            .ok()?
+            .read_to_string(&mut content)
+            .await
+            .ok()?;
+
+        let mut lines = content.lines();
+
+        if lines.next()? != header {
+            return None;
+        }
+
+        Some(lines.filter_map(|line| line.parse().ok()).collect())
+    }
+
+    async fn record(&self, index: usize) -> ChuaResult<()> {
+        let mut file = self.file.lock().await;
+
+        file.write_all(format!("{}\n", index).as_bytes()).await?;
+        file.sync_data().await?;
+
+        Ok(())
+    }
+}
+
+async fn file_md5(path: &Path) -> ChuaResult<String> {
+    let mut file = File::open(path).await?;
+    let mut context = md5::Context::new();
+    let mut buf = vec![0; 1024 * 1024];
+
+    loop {
+        let n = file.read(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+        context.consume(&buf[..n]);
+    }
+
+    Ok(format!("{:x}", context.compute()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 每个测试用自己的目录，避免并行执行时互相影响
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("chua-download-{}-{}", std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn sidecar(target: &Path) -> PathBuf {
+        let mut p = OsString::from(target.as_os_str());
+        p.push(PROGRESS_SUFFIX);
+        PathBuf::from(p)
+    }
+
+    #[test]
+    fn pending_covers_the_tail_chunk() {
+        assert_eq!(
+            pending(10, 4, &HashSet::new()),
+            vec![(0, 0..4), (1, 4..8), (2, 8..10)]
+        );
+    }
+
+    #[tokio::test]
+    async fn resumes_from_the_sidecar() {
+        let dir = temp_dir("resume");
+        let target = dir.join("file");
+
+        std::fs::write(&target, b"abcd\0\0\0\0ij").unwrap();
+        std::fs::write(sidecar(&target), "10 4\n0\n2\n").unwrap();
+
+        let progress = Progress::open(&target, 10, 4).await.unwrap();
+
+        assert_eq!(pending(10, 4, &progress.done), vec![(1, 4..8)]);
+
+        // 已下载的内容原样保留
+        assert_eq!(std::fs::read(&target).unwrap(), b"abcd\0\0\0\0ij");
+
+        progress.record(1).await.unwrap();
+        drop(progress);
+
+        let progress = Progress::open(&target, 10, 4).await.unwrap();
+        assert!(pending(10, 4, &progress.done).is_empty());
+    }
+
+    #[tokio::test]
+    async fn restarts_when_the_sidecar_does_not_match() {
+        let dir = temp_dir("mismatch");
+        let target = dir.join("file");
+
+        std::fs::write(&target, b"abcdefgh").unwrap();
+        std::fs::write(sidecar(&target), "8 3\n0\n1\n").unwrap();
+
+        let progress = Progress::open(&target, 10, 4).await.unwrap();
+
+        assert_eq!(pending(10, 4, &progress.done).len(), 3);
+        assert_eq!(std::fs::metadata(&target).unwrap().len(), 10);
+        assert_eq!(std::fs::read_to_string(sidecar(&target)).unwrap(), "10 4\n");
+    }
+
+    #[tokio::test]
+    async fn restarts_when_the_target_is_missing() {
+        let dir = temp_dir("missing");
+        let target = dir.join("file");
+
+        std::fs::write(sidecar(&target), "10 4\n0\n1\n2\n").unwrap();
+
+        let progress = Progress::open(&target, 10, 4).await.unwrap();
+
+        assert_eq!(pending(10, 4, &progress.done).len(), 3);
+        assert!(target.is_file());
+    }
+}
@@ -1,68 +1,261 @@
-use crate::common::{ChuaError, Chunk, ChunkIterator};
 use crate::ChuaResult;
-use futures::future::join;
-use futures::StreamExt;
-use futures_channel::{mpsc, oneshot};
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::ops::Range;
 use std::path::Path;
-use tokio::fs::File;
-use tokio::prelude::*;
+use std::sync::Arc;
+use tokio::task::spawn_blocking;
 
-#[derive(Debug)]
+/// 按位置读取文件，可以被多个上传任务同时使用，每个任务直接读取自己分到的分片
+#[derive(Debug, Clone)]
 pub(super) struct FileReader {
-    size_iter: ChunkIterator,
-    file: File,
+    source: Source,
+    size: u64,
+}
+
+#[derive(Debug, Clone)]
+enum Source {
+    File(Arc<File>),
+
+    /// 已在内存中的内容，如预处理后的图片
+    #[cfg(feature = "preprocess")]
+    Memory(Arc<Vec<u8>>),
 }
 
 impl FileReader {
-    pub async fn new<P: AsRef<Path>>(path: P, chunk_size: u64) -> ChuaResult<(Self, u64)> {
-        let file = File::open(&path).await?;
+    pub async fn open<P: AsRef<Path>>(path: P) -> ChuaResult<Self> {
+        let path = path.as_ref().to_owned();
+
+        let (file, size) = blocking(move || {
+            let file = File::open(path)?;
+            let size = file.metadata()?.len();
+            Ok((file, size))
+        })
+        .await?;
 
-        let meta = file.metadata().await?;
+        Ok(Self {
+            source: Source::File(Arc::new(file)),
+            size,
+        })
+    }
 
-        let size = meta.len();
+    /// 读取已经打开的文件，如从其它进程得到的文件描述符，文件必须支持按位置读取
+    pub async fn from_file(file: File) -> ChuaResult<Self> {
+        let (file, size) = blocking(move || {
+            let metadata = file.metadata()?;
+            if !metadata.is_file() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "The file is not a regular file",
+                ));
+            }
+            Ok((file, metadata.len()))
+        })
+        .await?;
 
-        let size_iter = ChunkIterator::new(size, chunk_size);
+        Ok(Self {
+            source: Source::File(Arc::new(file)),
+            size,
+        })
+    }
 
-        Ok((Self { size_iter, file }, size))
+    #[cfg(feature = "preprocess")]
+    pub fn from_memory(data: Vec<u8>) -> Self {
+        Self {
+            size: data.len() as u64,
+            source: Source::Memory(Arc::new(data)),
+        }
     }
 
-    async fn read_chunk(&mut self) -> Option<ChuaResult<Chunk<Vec<u8>>>> {
-        let next_pos = self.size_iter.next();
-
-        match next_pos {
-            None => None,
-            Some((index, range)) => {
-                let size = range.end - range.start;
-                let mut data = vec![0; size as usize];
-                match self.file.read_exact(&mut data).await {
-                    Ok(_) => Some(Ok(Chunk { index, data })),
-                    Err(e) => Some(Err(e.into())),
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// 读取 `range` 范围内的数据
+    pub async fn read(&self, range: Range<u64>) -> ChuaResult<Vec<u8>> {
+        let file = match &self.source {
+            Source::File(file) => file.clone(),
+            #[cfg(feature = "preprocess")]
+            Source::Memory(data) => {
+                return match data.get(range.start as usize..range.end as usize) {
+                    Some(data) => Ok(data.to_vec()),
+                    None => Err(io::Error::from(io::ErrorKind::UnexpectedEof).into()),
                 }
             }
+        };
+
+        blocking(move || {
+            let mut data = vec![0; (range.end - range.start) as usize];
+            read_exact_at(&file, &mut data, range.start)?;
+            Ok(data)
+        })
+        .await
+    }
+}
+
+/// 按位置写入文件，可以被多个下载任务同时使用，每个任务直接写入自己分到的分片
+#[derive(Debug, Clone)]
+pub(super) struct FileWriter {
+    file: Arc<File>,
+}
+
+impl FileWriter {
+    /// 打开已存在的文件，不截断已有内容
+    pub async fn open<P: AsRef<Path>>(path: P) -> ChuaResult<Self> {
+        let path = path.as_ref().to_owned();
+
+        let file = blocking(move || OpenOptions::new().write(true).open(path)).await?;
+
+        Ok(Self {
+            file: Arc::new(file),
+        })
+    }
+
+    /// 把 `data` 写到 `offset` 处并落盘
+    pub async fn write(&self, offset: u64, data: Vec<u8>) -> ChuaResult<()> {
+        let file = self.file.clone();
+
+        blocking(move || {
+            write_all_at(&file, &data, offset)?;
+            file.sync_data()
+        })
+        .await
+    }
+}
+
+async fn blocking<F, T>(f: F) -> ChuaResult<T>
+where
+    F: FnOnce() -> io::Result<T> + Send + 'static,
+    T: Send + 'static,
+{
+    match spawn_blocking(f).await {
+        Ok(result) => Ok(result?),
+        Err(e) => Err(e.to_string().into()),
+    }
+}
+
+#[cfg(unix)]
+fn read_exact_at(file: &File, buf: &mut [u8], offset: u64) -> io::Result<()> {
+    use std::os::unix::fs::FileExt;
+
+    file.read_exact_at(buf, offset)
+}
+
+#[cfg(unix)]
+fn write_all_at(file: &File, buf: &[u8], offset: u64) -> io::Result<()> {
+    use std::os::unix::fs::FileExt;
+
+    file.write_all_at(buf, offset)
+}
+
+#[cfg(windows)]
+fn write_all_at(file: &File, mut buf: &[u8], mut offset: u64) -> io::Result<()> {
+    use std::os::windows::fs::FileExt;
+
+    while !buf.is_empty() {
+        match file.seek_write(buf, offset) {
+            Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+            Ok(n) => {
+                buf = &buf[n..];
+                offset += n as u64;
+            }
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
         }
     }
 
-    pub(crate) async fn run(
-        mut self,
-        mut receiver: mpsc::UnboundedReceiver<oneshot::Sender<Option<Chunk<Vec<u8>>>>>,
-    ) -> Result<(), ChuaError> {
-        while let (Some(sender), read_chunk) = join(receiver.next(), self.read_chunk()).await {
-            match read_chunk {
-                Some(result) => match result {
-                    Ok(chunk) => sender
-                        .send(Some(chunk))
-                        .map_err(|_| format!("cannot send data to send_loop"))?,
-                    Err(e) => return Err(e.into()),
-                },
-                None => {
-                    sender
-                        .send(None)
-                        .map_err(|_| format!("cannot send EOF to send_loop"))?;
-                    break;
-                }
+    Ok(())
+}
+
+#[cfg(windows)]
+fn read_exact_at(file: &File, mut buf: &mut [u8], mut offset: u64) -> io::Result<()> {
+    use std::os::windows::fs::FileExt;
+
+    while !buf.is_empty() {
+        match file.seek_read(buf, offset) {
+            Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+            Ok(n) => {
+                buf = &mut buf[n..];
+                offset += n as u64;
             }
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::ChunkIterator;
+    use std::path::PathBuf;
+
+    /// 10000 字节，按 3000 分片时最后一片只有 1000 字节
+    fn test_file(name: &str) -> (PathBuf, Vec<u8>) {
+        let path = std::env::temp_dir().join(format!("chua-file-{}-{}", std::process::id(), name));
+        let data: Vec<u8> = (0..10_000u32).map(|i| (i % 251) as u8).collect();
+        std::fs::write(&path, &data).unwrap();
+        (path, data)
+    }
+
+    /// 同时读取所有分片，顺序打乱，检查每一片的内容
+    async fn check_ranges(reader: &FileReader, data: &[u8]) {
+        let mut chunks: Vec<_> = ChunkIterator::new(reader.size(), 3000).collect();
+        chunks.reverse();
+        chunks.swap(0, 2);
+
+        let read =
+            futures::future::join_all(chunks.iter().map(|(_, range)| reader.read(range.clone())))
+                .await;
+
+        for ((index, range), result) in chunks.iter().zip(read) {
+            let chunk = result.unwrap();
+            assert_eq!(
+                chunk,
+                &data[range.start as usize..range.end as usize],
+                "chunk {}",
+                index
+            );
         }
 
-        Ok(())
+        // 包括不足 3000 字节的最后一片
+        assert!(chunks.contains(&(3, 9000..10_000)));
+    }
+
+    #[tokio::test]
+    async fn reads_ranges_concurrently() {
+        let (path, data) = test_file("ranges");
+        let reader = FileReader::open(&path).await.unwrap();
+
+        assert_eq!(reader.size(), 10_000);
+        check_ranges(&reader, &data).await;
+
+        // 不相邻的小范围
+        assert_eq!(reader.read(1..4).await.unwrap(), &data[1..4]);
+        assert_eq!(reader.read(9_998..10_000).await.unwrap(), &data[9_998..]);
+        assert!(reader.read(9_999..10_001).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn reads_ranges_from_an_open_file() {
+        let (path, data) = test_file("open-file");
+
+        // 与 Android 中交给 `upload_file` 的文件描述符一样，文件已由调用方打开
+        let reader = FileReader::from_file(File::open(&path).unwrap())
+            .await
+            .unwrap();
+
+        assert_eq!(reader.size(), 10_000);
+        check_ranges(&reader, &data).await;
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn rejects_files_that_are_not_regular() {
+        let dir = File::open(std::env::temp_dir()).unwrap();
+
+        assert!(FileReader::from_file(dir).await.is_err());
     }
 }
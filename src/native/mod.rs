@@ -1,61 +1,395 @@
+mod download;
 mod file;
+#[cfg(feature = "preprocess")]
+mod preprocess;
 
-use crate::common::{ChuaError, Uploader};
-use crate::{ChuaResult, CompleteResult, InitializeParam, InitializeResult};
+use crate::common::{ChuaError, ChunkIterator, ChunkQueue, EventSender, Uploader};
+use crate::{
+    AppendParam, CancelToken, Capability, ChuaResult, ClientOptions, FileInfo, InitializeParam,
+    UploadEvents, UploadSession, UploadState, CAPABILITIES, PROTOCOL_VERSION,
+};
 use file::FileReader;
-use futures_channel::mpsc;
+use futures::Future;
 use reqwest::IntoUrl;
+use std::fs::File;
+use std::ops::Range;
 use std::path::Path;
-use std::time::Duration;
 use uuid::Uuid;
 
+pub use download::{download, download_with_events};
+#[cfg(feature = "preprocess")]
+pub use preprocess::ImageOptions;
+
+/// 计算前缀 md5 时每次读取的大小
+const HASH_BLOCK_SIZE: u64 = 1024 * 1024;
+
 pub async fn upload(
     base_url: impl IntoUrl,
     path: impl AsRef<Path>,
     chunk_size: u64,
     parallel: usize,
+) -> ChuaResult<Uuid> {
+    upload_with_options(
+        base_url,
+        path,
+        chunk_size,
+        parallel,
+        &ClientOptions::default(),
+    )
+    .await
+}
+
+/// 与 [`upload`] 相同，使用自定义的 HTTP 客户端配置
+pub async fn upload_with_options(
+    base_url: impl IntoUrl,
+    path: impl AsRef<Path>,
+    chunk_size: u64,
+    parallel: usize,
+    options: &ClientOptions,
+) -> ChuaResult<Uuid> {
+    let uploader = Uploader::new(base_url, options, EventSender::default()).await?;
+
+    upload_with(
+        uploader,
+        path,
+        None,
+        chunk_size,
+        parallel,
+        FileInfo::default(),
+    )
+    .await
+}
+
+/// 与 [`upload_with_options`] 相同，同时提交文件名、MIME 类型和自定义元数据
+pub async fn upload_with_info(
+    base_url: impl IntoUrl,
+    path: impl AsRef<Path>,
+    chunk_size: u64,
+    parallel: usize,
+    info: FileInfo,
+    options: &ClientOptions,
+) -> ChuaResult<Uuid> {
+    let uploader = Uploader::new(base_url, options, EventSender::default()).await?;
+
+    upload_with(uploader, path, None, chunk_size, parallel, info).await
+}
+
+/// 与 [`upload_with_info`] 相同，但 JPEG/PNG 图片会先按 `image` 缩放、重新编码并去掉
+/// EXIF/GPS 等元数据，初始化时提交处理后的大小。其它文件按原样上传。
+#[cfg(feature = "preprocess")]
+pub async fn upload_image(
+    base_url: impl IntoUrl,
+    path: impl AsRef<Path>,
+    chunk_size: u64,
+    parallel: usize,
+    image: &ImageOptions,
+    info: FileInfo,
+    options: &ClientOptions,
 ) -> ChuaResult<Uuid> {
     let path = path.as_ref();
-    if !path.is_file() {
-        return Err(ChuaError::Other(
-            "The path is not pointing a regular file".into(),
-        ));
-    }
+    let (reader, extension) = open(path).await?;
 
-    let extension = match path.extension() {
-        None => String::new(),
-        Some(ext) => ext.to_str().unwrap_or("").to_string(),
+    let info = FileInfo {
+        name: info.name.or_else(|| Some(file_name(path))),
+        ..info
     };
 
-    let (reader, size) = FileReader::new(path, chunk_size).await?;
+    let reader = if preprocess::supports(&extension) {
+        let data = reader.read(0..reader.size()).await?;
+        let (ext, image) = (extension.clone(), image.clone());
 
-    let uploader = Uploader::new(base_url, Duration::from_secs(20)).await?;
+        // 解码和编码都很耗 CPU，不能占用异步任务的线程
+        let data =
+            match tokio::task::spawn_blocking(move || preprocess::preprocess(&data, &ext, &image))
+                .await
+            {
+                Ok(result) => result?,
+                Err(e) => return Err(e.to_string().into()),
+            };
 
-    let init_param = InitializeParam {
-        size,
+        FileReader::from_memory(data)
+    } else {
+        reader
+    };
+
+    let uploader = Uploader::new(base_url, options, EventSender::default()).await?;
+    let range = 0..reader.size();
+
+    upload_reader(
+        uploader, reader, extension, range, chunk_size, parallel, info,
+    )
+    .await
+}
+
+/// 与 [`upload_with_options`] 相同，同时返回上传过程的事件流
+pub fn upload_with_events(
+    base_url: impl IntoUrl,
+    path: impl AsRef<Path>,
+    chunk_size: u64,
+    parallel: usize,
+    options: ClientOptions,
+) -> (impl Future<Output = ChuaResult<Uuid>>, UploadEvents) {
+    upload_cancelable(
+        base_url,
+        path,
+        chunk_size,
+        parallel,
+        FileInfo::default(),
+        options,
+        CancelToken::new(),
+    )
+}
+
+/// 与 [`upload_with_events`] 相同，同时提交 `info`。`cancel` 被取消时停止上传，并通知服务端丢弃
+/// 已上传的分片
+pub fn upload_cancelable(
+    base_url: impl IntoUrl,
+    path: impl AsRef<Path>,
+    chunk_size: u64,
+    parallel: usize,
+    info: FileInfo,
+    options: ClientOptions,
+    cancel: CancelToken,
+) -> (impl Future<Output = ChuaResult<Uuid>>, UploadEvents) {
+    let (events, stream) = EventSender::channel();
+
+    let future = async move {
+        let uploader = Uploader::new(base_url, &options, events)
+            .await?
+            .with_cancel(cancel);
+
+        upload_with(uploader, path, None, chunk_size, parallel, info).await
+    };
+
+    (future, stream)
+}
+
+/// 与 [`upload_cancelable`] 相同，但上传已经打开的 `file`，如 Android 中从 content URI 得到的
+/// 文件描述符。扩展名取自 `info.name`
+pub fn upload_file(
+    base_url: impl IntoUrl,
+    file: File,
+    chunk_size: u64,
+    parallel: usize,
+    info: FileInfo,
+    options: ClientOptions,
+    cancel: CancelToken,
+) -> (impl Future<Output = ChuaResult<Uuid>>, UploadEvents) {
+    let (events, stream) = EventSender::channel();
+
+    let future = async move {
+        let reader = FileReader::from_file(file).await?;
+
+        let extension = match &info.name {
+            Some(name) => extension(Path::new(name)),
+            None => String::new(),
+        };
+
+        let uploader = Uploader::new(base_url, &options, events)
+            .await?
+            .with_cancel(cancel);
+        let range = 0..reader.size();
+
+        upload_reader(
+            uploader, reader, extension, range, chunk_size, parallel, info,
+        )
+        .await
+    };
+
+    (future, stream)
+}
+
+/// 接续上传 `file_id`：查询服务端已收到的分片，只上传缺少的分片并完成上传
+pub async fn resume(
+    base_url: impl IntoUrl,
+    file_id: Uuid,
+    path: impl AsRef<Path>,
+    parallel: usize,
+    options: &ClientOptions,
+) -> ChuaResult<Uuid> {
+    let (reader, _) = open(path.as_ref()).await?;
+
+    let uploader = Uploader::new(base_url, options, EventSender::default()).await?;
+
+    let (session, status) = UploadSession::resume(uploader, file_id, reader.size()).await?;
+
+    if status.state == UploadState::Completed {
+        return Ok(file_id);
+    }
+
+    let queue = ChunkQueue::missing(0..reader.size(), status.chunk_size, status.missing());
+
+    transfer(session, reader, queue, parallel).await
+}
+
+/// 只把本地文件中 `range` 这一段作为一个新文件上传
+pub async fn upload_range(
+    base_url: impl IntoUrl,
+    path: impl AsRef<Path>,
+    range: Range<u64>,
+    chunk_size: u64,
+    parallel: usize,
+    options: &ClientOptions,
+) -> ChuaResult<Uuid> {
+    let uploader = Uploader::new(base_url, options, EventSender::default()).await?;
+
+    upload_with(
+        uploader,
+        path,
+        Some(range),
+        chunk_size,
+        parallel,
+        FileInfo::default(),
+    )
+    .await
+}
+
+/// 把本地文件 `offset` 之后的内容追加到服务端已完成的文件 `file_id` 末尾。
+///
+/// `offset` 是服务端文件的大小，服务端会校验本地文件前 `offset` 字节的 md5 与已有文件一致。
+pub async fn append(
+    base_url: impl IntoUrl,
+    file_id: Uuid,
+    path: impl AsRef<Path>,
+    offset: u64,
+    chunk_size: u64,
+    parallel: usize,
+    options: &ClientOptions,
+) -> ChuaResult<Uuid> {
+    let path = path.as_ref();
+
+    let (reader, extension) = open(path).await?;
+    let size = reader.size();
+
+    if offset > size {
+        return Err(format!("Offset {} is beyond the end of the file ({})", offset, size).into());
+    }
+
+    if offset == size {
+        // 没有新内容
+        return Ok(file_id);
+    }
+
+    let prefix_md5 = md5(&reader, 0..offset).await?;
+
+    let uploader = Uploader::new(base_url, options, EventSender::default()).await?;
+
+    let param = AppendParam {
+        offset,
+        size: size - offset,
         chunk_size,
         extension,
+        prefix_md5,
         md5: "".to_string(),
     };
 
-    let file_id = match uploader.initialize(init_param).await {
-        Ok(result) => match result {
-            InitializeResult::Ok { id, duplicated } => {
-                if duplicated {
-                    return Ok(id);
-                }
+    let session = UploadSession::start_append(uploader, file_id, param).await?;
 
-                id
-            }
-            InitializeResult::Err { error } => return Err(format!("{:?}", error).into()),
-        },
-        Err(e) => return Err(e),
+    let queue = ChunkQueue::new(offset..size, session.chunk_size());
+
+    transfer(session, reader, queue, parallel).await
+}
+
+async fn upload_with(
+    uploader: Uploader,
+    path: impl AsRef<Path>,
+    range: Option<Range<u64>>,
+    chunk_size: u64,
+    parallel: usize,
+    info: FileInfo,
+) -> ChuaResult<Uuid> {
+    let path = path.as_ref();
+    let (reader, extension) = open(path).await?;
+
+    let info = FileInfo {
+        name: info.name.or_else(|| Some(file_name(path))),
+        ..info
     };
 
-    let (sender, receiver) = mpsc::unbounded();
+    let range = match range {
+        Some(range) if range.start > range.end || range.end > reader.size() => {
+            return Err(format!(
+                "Range {:?} is out of the file (0..{})",
+                range,
+                reader.size()
+            )
+            .into())
+        }
+        Some(range) => range,
+        None => 0..reader.size(),
+    };
+
+    upload_reader(
+        uploader, reader, extension, range, chunk_size, parallel, info,
+    )
+    .await
+}
+
+/// 把 `reader` 中 `range` 这一段作为一个新文件上传
+async fn upload_reader(
+    uploader: Uploader,
+    reader: FileReader,
+    extension: String,
+    range: Range<u64>,
+    chunk_size: u64,
+    parallel: usize,
+    info: FileInfo,
+) -> ChuaResult<Uuid> {
+    let init_param = InitializeParam {
+        size: range.end - range.start,
+        chunk_size,
+        extension,
+        md5: info.md5.unwrap_or_default(),
+        name: info.name.unwrap_or_default(),
+        content_type: info.content_type.unwrap_or_default(),
+        metadata: info.metadata,
+        version: PROTOCOL_VERSION,
+        capabilities: CAPABILITIES.to_vec(),
+    };
 
-    tokio::spawn(reader.run(receiver));
+    let session = UploadSession::start(uploader, init_param).await?;
 
+    if session.duplicated() {
+        return Ok(session.id());
+    }
+
+    let queue = ChunkQueue::new(range, session.chunk_size());
+
+    transfer(session, reader, queue, parallel).await
+}
+
+/// 打开要上传的文件，同时取出扩展名
+async fn open(path: &Path) -> ChuaResult<(FileReader, String)> {
+    if !path.is_file() {
+        return Err(ChuaError::Other(
+            "The path is not pointing a regular file".into(),
+        ));
+    }
+
+    Ok((FileReader::open(path).await?, extension(path)))
+}
+
+fn extension(path: &Path) -> String {
+    match path.extension() {
+        None => String::new(),
+        Some(ext) => ext.to_str().unwrap_or("").to_string(),
+    }
+}
+
+fn file_name(path: &Path) -> String {
+    match path.file_name() {
+        None => String::new(),
+        Some(name) => name.to_string_lossy().into_owned(),
+    }
+}
+
+/// 并行上传 `queue` 中的所有分片并完成上传。会话被取消时通知服务端放弃上传
+async fn transfer(
+    session: UploadSession,
+    reader: FileReader,
+    queue: ChunkQueue,
+    parallel: usize,
+) -> ChuaResult<Uuid> {
     let parallel = if parallel == 0 {
         num_cpus::get()
     } else {
@@ -65,15 +399,66 @@ pub async fn upload(
     let mut vec = Vec::with_capacity(parallel);
 
     for _ in 0..parallel {
-        let uploader = uploader.clone();
-        vec.push(tokio::spawn(uploader.upload_chunk(file_id, sender.clone())));
+        let session = session.clone();
+        let reader = reader.clone();
+        let queue = queue.clone();
+
+        vec.push(tokio::spawn(async move {
+            let cancel = session.cancel_token().clone();
+            cancel.run(upload_chunks(session, reader, queue)).await
+        }));
+    }
+
+    let results = futures::future::join_all(vec).await;
+
+    if session.cancel_token().is_canceled() {
+        // 服务端不支持放弃时分片留在服务端，由它自行清理
+        if session.supports(Capability::Abort) {
+            if let Err(e) = session.abort().await {
+                log::warn!("Failed to abort upload {}: {}", session.id(), e);
+            }
+        }
+
+        return Err(ChuaError::Aborted);
+    }
+
+    // 有分片没传上去时不能合并，返回第一个错误，服务端保留已收到的分片以便接续
+    for result in results {
+        let result =
+            result.map_err(|e| ChuaError::Other(format!("The upload task failed: {}", e)))?;
+
+        if let Some(Err(e)) = result {
+            return Err(e);
+        }
+    }
+
+    session.complete().await?;
+
+    Ok(session.id())
+}
+
+/// 不断从 `queue` 领取分片，读取并上传，直到分片领完
+async fn upload_chunks(
+    session: UploadSession,
+    reader: FileReader,
+    queue: ChunkQueue,
+) -> ChuaResult<()> {
+    while let Some((index, range)) = queue.next() {
+        let data = reader.read(range).await?;
+
+        session.upload_chunk(index, data).await?;
     }
 
-    let _ = futures::future::join_all(vec).await;
+    Ok(())
+}
+
+/// 计算文件中 `range` 这一段的 md5
+async fn md5(reader: &FileReader, range: Range<u64>) -> ChuaResult<String> {
+    let mut context = md5::Context::new();
 
-    if let CompleteResult::Err { error } = uploader.complete(&file_id).await? {
-        return Err(format!("{:?}", error).into());
+    for (_, block) in ChunkIterator::with_range(range, HASH_BLOCK_SIZE) {
+        context.consume(reader.read(block).await?);
     }
 
-    Ok(file_id)
+    Ok(format!("{:x}", context.compute()))
 }
@@ -0,0 +1,257 @@
+use crate::ChuaResult;
+use image::codecs::jpeg::JpegEncoder;
+use image::imageops::FilterType;
+use image::{DynamicImage, GenericImageView, ImageFormat, ImageOutputFormat};
+use std::io::Cursor;
+
+/// 图片预处理的配置
+#[derive(Debug, Clone)]
+pub struct ImageOptions {
+    /// 长边的最大像素数，超过时等比缩小，为 `None` 时保持原尺寸
+    pub max_dimension: Option<u32>,
+
+    /// JPEG 重新编码的质量（1-100）
+    pub quality: u8,
+}
+
+impl Default for ImageOptions {
+    fn default() -> Self {
+        Self {
+            max_dimension: None,
+            quality: 85,
+        }
+    }
+}
+
+/// 是否是支持预处理的图片格式
+pub(super) fn supports(extension: &str) -> bool {
+    format(extension).is_some()
+}
+
+fn format(extension: &str) -> Option<ImageFormat> {
+    match ImageFormat::from_extension(extension) {
+        Some(format @ ImageFormat::Jpeg) | Some(format @ ImageFormat::Png) => Some(format),
+        _ => None,
+    }
+}
+
+/// 按 `options` 缩放并重新编码图片，格式不变。重新编码不会写入 EXIF/GPS 等元数据，
+/// EXIF 中的方向会先应用到像素上。
+pub(super) fn preprocess(
+    data: &[u8],
+    extension: &str,
+    options: &ImageOptions,
+) -> ChuaResult<Vec<u8>> {
+    let format = match format(extension) {
+        Some(format) => format,
+        None => return Err(format!("Unsupported image format: {}", extension).into()),
+    };
+
+    let mut image = image::load_from_memory_with_format(data, format)?;
+
+    if format == ImageFormat::Jpeg {
+        image = orient(image, orientation(data));
+    }
+
+    if let Some(max) = options.max_dimension {
+        let (width, height) = image.dimensions();
+        if width > max || height > max {
+            image = image.resize(max, max, FilterType::Lanczos3);
+        }
+    }
+
+    let mut buf = Vec::new();
+
+    if format == ImageFormat::Jpeg {
+        JpegEncoder::new_with_quality(&mut buf, options.quality.clamp(1, 100))
+            .encode_image(&image)?;
+    } else {
+        image.write_to(&mut buf, ImageOutputFormat::Png)?;
+    }
+
+    Ok(buf)
+}
+
+/// 读取 EXIF 中的方向，没有时为 1（正常）
+fn orientation(data: &[u8]) -> u32 {
+    exif::Reader::new()
+        .read_from_container(&mut Cursor::new(data))
+        .ok()
+        .and_then(|exif| {
+            exif.get_field(exif::Tag::Orientation, exif::In::PRIMARY)
+                .and_then(|field| field.value.get_uint(0))
+        })
+        .unwrap_or(1)
+}
+
+fn orient(image: DynamicImage, orientation: u32) -> DynamicImage {
+    match orientation {
+        2 => image.fliph(),
+        3 => image.rotate180(),
+        4 => image.flipv(),
+        5 => image.rotate90().fliph(),
+        6 => image.rotate90(),
+        7 => image.rotate270().fliph(),
+        8 => image.rotate270(),
+        _ => image,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use exif::experimental::Writer;
+    use exif::{Field, In, Tag, Value};
+    use image::{Rgb, RgbImage};
+
+    /// 40x20 的 JPEG，左半红色、右半蓝色，带方向为 6（需顺时针旋转 90 度）和 GPS 的 EXIF
+    fn photo() -> Vec<u8> {
+        let image = RgbImage::from_fn(40, 20, |x, _| {
+            if x < 20 {
+                Rgb([255, 0, 0])
+            } else {
+                Rgb([0, 0, 255])
+            }
+        });
+
+        let mut jpeg = Vec::new();
+        JpegEncoder::new_with_quality(&mut jpeg, 95)
+            .encode_image(&DynamicImage::ImageRgb8(image))
+            .unwrap();
+
+        let orientation = Field {
+            tag: Tag::Orientation,
+            ifd_num: In::PRIMARY,
+            value: Value::Short(vec![6]),
+        };
+        let latitude = Field {
+            tag: Tag::GPSLatitudeRef,
+            ifd_num: In::PRIMARY,
+            value: Value::Ascii(vec![b"N".to_vec()]),
+        };
+
+        let mut writer = Writer::new();
+        writer.push_field(&orientation);
+        writer.push_field(&latitude);
+
+        let mut tiff = Cursor::new(Vec::new());
+        writer.write(&mut tiff, false).unwrap();
+        let tiff = tiff.into_inner();
+
+        // 在 SOI 之后插入 APP1 段
+        let mut data = jpeg[..2].to_vec();
+        data.extend_from_slice(&[0xFF, 0xE1]);
+        data.extend_from_slice(&((2 + 6 + tiff.len()) as u16).to_be_bytes());
+        data.extend_from_slice(b"Exif\0\0");
+        data.extend_from_slice(&tiff);
+        data.extend_from_slice(&jpeg[2..]);
+        data
+    }
+
+    fn has_exif(data: &[u8]) -> bool {
+        exif::Reader::new()
+            .read_from_container(&mut Cursor::new(data))
+            .is_ok()
+    }
+
+    fn is_red(pixel: Rgb<u8>) -> bool {
+        pixel[0] > 200 && pixel[2] < 60
+    }
+
+    fn is_blue(pixel: Rgb<u8>) -> bool {
+        pixel[2] > 200 && pixel[0] < 60
+    }
+
+    #[test]
+    fn the_fixture_has_orientation_and_gps() {
+        let data = photo();
+
+        assert_eq!(orientation(&data), 6);
+
+        let exif = exif::Reader::new()
+            .read_from_container(&mut Cursor::new(&data))
+            .unwrap();
+        assert!(exif.get_field(Tag::GPSLatitudeRef, In::PRIMARY).is_some());
+    }
+
+    #[test]
+    fn applies_the_orientation_and_strips_exif() {
+        let output = preprocess(&photo(), "jpg", &ImageOptions::default()).unwrap();
+
+        assert!(!has_exif(&output));
+
+        let image = image::load_from_memory_with_format(&output, ImageFormat::Jpeg)
+            .unwrap()
+            .to_rgb8();
+
+        // 顺时针旋转 90 度后宽高互换，原来的左半到了上半
+        assert_eq!(image.dimensions(), (20, 40));
+        assert!(is_red(*image.get_pixel(10, 5)));
+        assert!(is_blue(*image.get_pixel(10, 35)));
+    }
+
+    #[test]
+    fn limits_the_longer_side() {
+        let options = ImageOptions {
+            max_dimension: Some(10),
+            ..ImageOptions::default()
+        };
+
+        let output = preprocess(&photo(), "jpg", &options).unwrap();
+
+        assert!(!has_exif(&output));
+        assert_eq!(
+            image::load_from_memory(&output).unwrap().dimensions(),
+            (5, 10)
+        );
+    }
+
+    #[test]
+    fn keeps_small_images_at_their_size() {
+        let options = ImageOptions {
+            max_dimension: Some(100),
+            ..ImageOptions::default()
+        };
+
+        let output = preprocess(&photo(), "jpg", &options).unwrap();
+
+        assert_eq!(
+            image::load_from_memory(&output).unwrap().dimensions(),
+            (20, 40)
+        );
+    }
+
+    #[test]
+    fn lower_quality_gives_smaller_files() {
+        // 噪点多的图片，质量对大小的影响明显
+        let image = RgbImage::from_fn(64, 64, |x, y| {
+            Rgb([(x * 37 + y * 11) as u8, (x * y) as u8, (x ^ y) as u8 * 4])
+        });
+        let mut jpeg = Vec::new();
+        JpegEncoder::new_with_quality(&mut jpeg, 100)
+            .encode_image(&DynamicImage::ImageRgb8(image))
+            .unwrap();
+
+        let quality = |quality| {
+            preprocess(
+                &jpeg,
+                "jpg",
+                &ImageOptions {
+                    max_dimension: None,
+                    quality,
+                },
+            )
+            .unwrap()
+            .len()
+        };
+
+        assert!(quality(10) < quality(90));
+    }
+
+    #[test]
+    fn rejects_other_formats() {
+        assert!(!supports("gif"));
+        assert!(supports("JPG"));
+        assert!(preprocess(b"GIF89a", "gif", &ImageOptions::default()).is_err());
+    }
+}
@@ -0,0 +1,101 @@
+use crate::wasm::runtime::promise;
+use crate::{ChuaError, ChuaResult, ClientOptions};
+use js_sys::{Promise, Uint8Array};
+use reqwest::Url;
+use wasm_bindgen::prelude::*;
+use web_sys::{AbortController, Request, RequestCredentials, RequestInit, Response};
+
+#[wasm_bindgen]
+extern "C" {
+    // 使用全局的 fetch 和 setTimeout，不依赖 window，在 Worker 中也能用
+    #[wasm_bindgen(js_name = fetch)]
+    fn fetch_with_request(request: &Request) -> Promise;
+
+    #[wasm_bindgen(js_name = setTimeout)]
+    fn set_timeout(handler: &Closure<dyn FnMut()>, timeout: i32) -> JsValue;
+
+    #[wasm_bindgen(js_name = clearTimeout)]
+    fn clear_timeout(handle: &JsValue);
+}
+
+/// 发送请求并返回响应体。请求失败、超时或状态码不是 2xx 时返回错误
+pub(crate) async fn fetch(
+    method: &str,
+    url: &Url,
+    body: Option<&JsValue>,
+    content_type: Option<&str>,
+    options: &ClientOptions,
+) -> ChuaResult<Vec<u8>> {
+    let controller = AbortController::new().map_err(js_error)?;
+    let signal = controller.signal();
+
+    let mut init = RequestInit::new();
+    init.method(method);
+    init.body(body);
+    init.signal(Some(&signal));
+
+    if options.credentials {
+        init.credentials(RequestCredentials::Include);
+    }
+
+    let request = Request::new_with_str_and_init(url.as_str(), &init).map_err(js_error)?;
+
+    if let Some(content_type) = content_type {
+        request
+            .headers()
+            .set("Content-Type", content_type)
+            .map_err(js_error)?;
+    }
+
+    // 超时后中断请求，fetch 会以 AbortError 失败
+    let abort: Closure<dyn FnMut()> = Closure::once(move || controller.abort());
+    let handle = set_timeout(
+        &abort,
+        options.timeout.as_millis().min(i32::MAX as u128) as i32,
+    );
+
+    let result = send(&request).await;
+
+    clear_timeout(&handle);
+    drop(abort);
+
+    match result {
+        Err(_) if signal.aborted() => Err(ChuaError::Network(format!(
+            "{} {} timed out after {:?}",
+            method, url, options.timeout
+        ))),
+        result => result,
+    }
+}
+
+async fn send(request: &Request) -> ChuaResult<Vec<u8>> {
+    // fetch 只在没有拿到响应时失败，属于网络错误
+    let response = promise::<Response>(fetch_with_request(request))
+        .await
+        .map_err(|e| match js_error(e) {
+            ChuaError::Other(message) => ChuaError::Network(message),
+            e => e,
+        })?;
+
+    if !response.ok() {
+        return Err(ChuaError::HttpStatus {
+            method: request.method(),
+            url: request.url(),
+            status: response.status(),
+        });
+    }
+
+    let buffer = promise::<JsValue>(response.array_buffer().map_err(js_error)?)
+        .await
+        .map_err(js_error)?;
+
+    Ok(Uint8Array::new(&buffer).to_vec())
+}
+
+/// 把 JS 异常转换成错误
+pub(crate) fn js_error(e: JsValue) -> ChuaError {
+    match e.dyn_ref::<js_sys::Error>() {
+        Some(error) => String::from(error.message()).into(),
+        None => format!("{:?}", e).into(),
+    }
+}
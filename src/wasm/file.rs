@@ -1,58 +1,26 @@
 use super::runtime::get_slice;
-use crate::common::{ChuaError, Chunk, ChunkIterator};
-use crate::ChuaResult;
-use futures::future::join;
-use futures::StreamExt;
-use futures_channel::{mpsc, oneshot};
+use std::ops::Range;
 
-#[derive(Debug)]
+/// 按位置切分 `Blob`，可以被多个上传任务同时使用
+#[derive(Debug, Clone)]
 pub(super) struct FileReader {
-    size_iter: ChunkIterator,
     file: web_sys::Blob,
+    size: u64,
 }
 
 impl FileReader {
-    pub fn new(file: web_sys::Blob, chunk_size: u64) -> (Self, u64) {
+    pub fn new(file: web_sys::Blob) -> Self {
         let size = file.size() as u64;
 
-        let size_iter = ChunkIterator::new(size, chunk_size);
-
-        (Self { size_iter, file }, size)
+        Self { file, size }
     }
 
-    async fn read_chunk(&mut self) -> Option<ChuaResult<Chunk<web_sys::Blob>>> {
-        let next_pos = self.size_iter.next();
-
-        match next_pos {
-            None => None,
-            Some((index, range)) => {
-                let data = get_slice(&self.file, range.start, range.end);
-                Some(Ok(Chunk { index, data }))
-            }
-        }
+    pub fn size(&self) -> u64 {
+        self.size
     }
 
-    pub(crate) async fn run(
-        mut self,
-        mut receiver: mpsc::UnboundedReceiver<oneshot::Sender<Option<Chunk<web_sys::Blob>>>>,
-    ) -> Result<(), ChuaError> {
-        while let (Some(sender), read_chunk) = join(receiver.next(), self.read_chunk()).await {
-            match read_chunk {
-                Some(result) => match result {
-                    Ok(chunk) => sender
-                        .send(Some(chunk))
-                        .map_err(|_| format!("cannot send data to send_loop"))?,
-                    Err(e) => return Err(e.into()),
-                },
-                None => {
-                    sender
-                        .send(None)
-                        .map_err(|_| format!("cannot send EOF to send_loop"))?;
-                    break;
-                }
-            }
-        }
-
-        Ok(())
+    /// 取出 `range` 范围内的数据
+    pub fn read(&self, range: Range<u64>) -> web_sys::Blob {
+        get_slice(&self.file, range.start, range.end)
     }
 }
@@ -1,72 +1,254 @@
+pub(crate) mod fetch;
 mod file;
 pub(crate) mod runtime;
 
-use crate::common::Uploader;
-use crate::{ChuaResult, CompleteResult, InitializeParam, InitializeResult};
+use crate::common::{ChunkQueue, EventSender, Uploader};
+use crate::{
+    CancelToken, Capability, ChuaError, ChuaResult, ClientOptions, FileInfo, InitializeParam,
+    UploadEvents, UploadSession, UploadState, CAPABILITIES, PROTOCOL_VERSION,
+};
 use file::FileReader;
-use futures_channel::mpsc;
+use futures::Future;
 use reqwest::IntoUrl;
 use uuid::Uuid;
+use wasm_bindgen::JsCast;
 
 pub async fn upload(
     base_url: impl IntoUrl,
-    file: web_sys::File,
+    file: impl Into<web_sys::Blob>,
     chunk_size: u64,
     parallel: usize,
 ) -> ChuaResult<Uuid> {
-    let name: String = file.name();
+    upload_with_options(
+        base_url,
+        file,
+        chunk_size,
+        parallel,
+        &ClientOptions::default(),
+    )
+    .await
+}
+
+/// 与 [`upload`] 相同，使用自定义的 HTTP 客户端配置
+pub async fn upload_with_options(
+    base_url: impl IntoUrl,
+    file: impl Into<web_sys::Blob>,
+    chunk_size: u64,
+    parallel: usize,
+    options: &ClientOptions,
+) -> ChuaResult<Uuid> {
+    let uploader = Uploader::new(base_url, options, EventSender::default()).await?;
+
+    upload_with(
+        uploader,
+        file.into(),
+        chunk_size,
+        parallel,
+        FileInfo::default(),
+    )
+    .await
+}
+
+/// 与 [`upload_with_options`] 相同，同时提交文件名、MIME 类型和自定义元数据
+pub async fn upload_with_info(
+    base_url: impl IntoUrl,
+    file: impl Into<web_sys::Blob>,
+    chunk_size: u64,
+    parallel: usize,
+    info: FileInfo,
+    options: &ClientOptions,
+) -> ChuaResult<Uuid> {
+    let uploader = Uploader::new(base_url, options, EventSender::default()).await?;
+
+    upload_with(uploader, file.into(), chunk_size, parallel, info).await
+}
+
+/// 与 [`upload_with_options`] 相同，同时返回上传过程的事件流
+pub fn upload_with_events(
+    base_url: impl IntoUrl,
+    file: impl Into<web_sys::Blob>,
+    chunk_size: u64,
+    parallel: usize,
+    options: ClientOptions,
+) -> (impl Future<Output = ChuaResult<Uuid>>, UploadEvents) {
+    upload_cancelable(
+        base_url,
+        file,
+        chunk_size,
+        parallel,
+        FileInfo::default(),
+        options,
+        CancelToken::new(),
+    )
+}
+
+/// 与 [`upload_with_events`] 相同，同时提交 `info`。`cancel` 被取消时停止上传，并通知服务端丢弃
+/// 已上传的分片
+pub fn upload_cancelable(
+    base_url: impl IntoUrl,
+    file: impl Into<web_sys::Blob>,
+    chunk_size: u64,
+    parallel: usize,
+    info: FileInfo,
+    options: ClientOptions,
+    cancel: CancelToken,
+) -> (impl Future<Output = ChuaResult<Uuid>>, UploadEvents) {
+    let file = file.into();
+    let (events, stream) = EventSender::channel();
+
+    let future = async move {
+        let uploader = Uploader::new(base_url, &options, events)
+            .await?
+            .with_cancel(cancel);
+
+        upload_with(uploader, file, chunk_size, parallel, info).await
+    };
+
+    (future, stream)
+}
+
+/// 接续上传 `file_id`：查询服务端已收到的分片，只上传缺少的分片并完成上传。
+/// 可用于页面刷新后，用户重新选择同一个文件时继续之前的上传
+pub fn resume_cancelable(
+    base_url: impl IntoUrl,
+    file_id: Uuid,
+    file: impl Into<web_sys::Blob>,
+    parallel: usize,
+    options: ClientOptions,
+    cancel: CancelToken,
+) -> (impl Future<Output = ChuaResult<Uuid>>, UploadEvents) {
+    let file = file.into();
+    let (events, stream) = EventSender::channel();
+
+    let future = async move {
+        let uploader = Uploader::new(base_url, &options, events)
+            .await?
+            .with_cancel(cancel);
+
+        let reader = FileReader::new(file);
+        let size = reader.size();
+
+        let (session, status) = UploadSession::resume(uploader, file_id, size).await?;
+
+        if status.state == UploadState::Completed {
+            return Ok(file_id);
+        }
+
+        let queue = ChunkQueue::missing(0..size, status.chunk_size, status.missing());
+
+        transfer(session, reader, queue, parallel).await
+    };
+
+    (future, stream)
+}
+
+async fn upload_with(
+    uploader: Uploader,
+    blob: web_sys::Blob,
+    chunk_size: u64,
+    parallel: usize,
+    info: FileInfo,
+) -> ChuaResult<Uuid> {
+    // 没有指定文件名时，`File` 使用它自己的文件名，其它 `Blob` 没有文件名
+    let name = match info.name {
+        Some(name) => name,
+        None => match blob.dyn_ref::<web_sys::File>() {
+            Some(file) => file.name(),
+            None => String::new(),
+        },
+    };
+
+    // 浏览器不认识的类型为空字符串，留给初始化时按扩展名推断
+    let content_type = info.content_type.unwrap_or_else(|| blob.type_());
 
     let extension = match name.rfind('.') {
         None => "".to_string(),
         Some(index) => name[index + 1..].to_string(),
     };
 
-    let (reader, size) = FileReader::new(file.into(), chunk_size);
-
-    let uploader = Uploader::new(base_url).await?;
+    let reader = FileReader::new(blob);
+    let size = reader.size();
 
     let init_param = InitializeParam {
         size,
         chunk_size,
         extension,
-        md5: "".to_string(),
+        md5: info.md5.unwrap_or_default(),
+        name,
+        content_type,
+        metadata: info.metadata,
+        version: PROTOCOL_VERSION,
+        capabilities: CAPABILITIES.to_vec(),
     };
 
-    let file_id = match uploader.initialize(init_param).await {
-        Ok(result) => match result {
-            InitializeResult::Ok { id, duplicated } => {
-                if duplicated {
-                    return Ok(id);
-                }
+    let session = UploadSession::start(uploader, init_param).await?;
 
-                id
-            }
-            InitializeResult::Err { error } => return Err(format!("{:?}", error).into()),
-        },
-        Err(e) => return Err(e),
-    };
+    if session.duplicated() {
+        return Ok(session.id());
+    }
 
-    let (sender, receiver) = mpsc::unbounded();
+    let queue = ChunkQueue::new(0..size, session.chunk_size());
 
-    runtime::spawn(async move { reader.run(receiver).await });
+    transfer(session, reader, queue, parallel).await
+}
 
+/// 并行上传 `queue` 中的所有分片并完成上传。会话被取消时通知服务端放弃上传
+async fn transfer(
+    session: UploadSession,
+    reader: FileReader,
+    queue: ChunkQueue,
+    parallel: usize,
+) -> ChuaResult<Uuid> {
     // Chrome 和 Firefox 的默认并行连接数都是 6
     let parallel = if parallel == 0 { 6 } else { parallel };
 
     let mut vec = Vec::with_capacity(parallel);
 
     for _ in 0..parallel {
-        let uploader = uploader.clone();
-        vec.push(runtime::spawn(
-            uploader.upload_chunk(file_id, sender.clone()),
-        ));
+        let session = session.clone();
+        let reader = reader.clone();
+        let queue = queue.clone();
+
+        vec.push(runtime::spawn(async move {
+            let cancel = session.cancel_token().clone();
+            cancel.run(upload_chunks(session, reader, queue)).await
+        }));
+    }
+
+    let results = futures::future::join_all(vec).await;
+
+    if session.cancel_token().is_canceled() {
+        // 服务端不支持放弃时分片留在服务端，由它自行清理
+        if session.supports(Capability::Abort) {
+            if let Err(e) = session.abort().await {
+                log::warn!("Failed to abort upload {}: {}", session.id(), e);
+            }
+        }
+
+        return Err(ChuaError::Aborted);
     }
 
-    let _ = futures::future::join_all(vec).await;
+    // 有分片没传上去时不能合并，返回第一个错误，服务端保留已收到的分片以便接续
+    for result in results {
+        if let Some(Err(e)) = result? {
+            return Err(e);
+        }
+    }
+
+    session.complete().await?;
+
+    Ok(session.id())
+}
 
-    if let CompleteResult::Err { error } = uploader.complete(&file_id).await? {
-        return Err(format!("{:?}", error).into());
+/// 不断从 `queue` 领取分片并上传，直到分片领完
+async fn upload_chunks(
+    session: UploadSession,
+    reader: FileReader,
+    queue: ChunkQueue,
+) -> ChuaResult<()> {
+    while let Some((index, range)) = queue.next() {
+        session.upload_chunk(index, reader.read(range)).await?;
     }
 
-    Ok(file_id)
+    Ok(())
 }
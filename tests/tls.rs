@@ -0,0 +1,202 @@
+//! 用私有 CA 签发的证书启动一个本地 HTTPS 服务，检查 `root_certificates` 和 `identity` 是否生效
+#![cfg(not(target_arch = "wasm32"))]
+
+use chua::{
+    upload_with_options, ClientOptions, CompleteResult, InitializeResult, UploadChunkResult,
+};
+use hyper::server::conn::Http;
+use hyper::service::service_fn;
+use hyper::{Body, Method, Request, Response};
+use rustls::internal::pemfile::{certs, pkcs8_private_keys};
+use rustls::{AllowAnyAnonymousOrAuthenticatedClient, RootCertStore, ServerConfig, Session};
+use std::convert::Infallible;
+use std::io::Cursor;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use tokio::net::TcpListener;
+use tokio_rustls::TlsAcceptor;
+use uuid::Uuid;
+
+const CA: &[u8] = include_bytes!("tls/ca.pem");
+const SERVER_CERT: &[u8] = include_bytes!("tls/server.pem");
+const SERVER_KEY: &[u8] = include_bytes!("tls/server.key");
+const CLIENT_IDENTITY: &[u8] = include_bytes!("tls/client-identity.pem");
+
+/// 本地的 HTTPS 服务，按协议应答上传请求，并记录每个连接出示的客户端证书
+struct Server {
+    port: u16,
+    peer_certificates: Arc<Mutex<Vec<Option<Vec<rustls::Certificate>>>>>,
+}
+
+impl Server {
+    async fn start() -> Self {
+        let mut roots = RootCertStore::empty();
+        roots.add_pem_file(&mut Cursor::new(CA)).unwrap();
+
+        let mut config = ServerConfig::new(AllowAnyAnonymousOrAuthenticatedClient::new(roots));
+        config
+            .set_single_cert(
+                certs(&mut Cursor::new(SERVER_CERT)).unwrap(),
+                pkcs8_private_keys(&mut Cursor::new(SERVER_KEY))
+                    .unwrap()
+                    .remove(0),
+            )
+            .unwrap();
+
+        let acceptor = TlsAcceptor::from(Arc::new(config));
+        let mut listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let peer_certificates = Arc::new(Mutex::new(Vec::new()));
+        let peers = peer_certificates.clone();
+
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = match listener.accept().await {
+                    Ok(accepted) => accepted,
+                    Err(_) => continue,
+                };
+
+                let acceptor = acceptor.clone();
+                let peers = peers.clone();
+
+                tokio::spawn(async move {
+                    // 不信任服务端证书的客户端会在握手时断开
+                    let stream = match acceptor.accept(stream).await {
+                        Ok(stream) => stream,
+                        Err(_) => return,
+                    };
+
+                    peers
+                        .lock()
+                        .unwrap()
+                        .push(stream.get_ref().1.get_peer_certificates());
+
+                    let _ = Http::new()
+                        .serve_connection(stream, service_fn(handle))
+                        .await;
+                });
+            }
+        });
+
+        Self {
+            port,
+            peer_certificates,
+        }
+    }
+
+    fn base_url(&self) -> String {
+        format!("https://localhost:{}/", self.port)
+    }
+
+    /// 已完成握手的连接数
+    fn connections(&self) -> usize {
+        self.peer_certificates.lock().unwrap().len()
+    }
+
+    /// 所有连接是否都出示了 `cert`
+    fn all_presented(&self, cert: &rustls::Certificate) -> bool {
+        self.peer_certificates
+            .lock()
+            .unwrap()
+            .iter()
+            .all(|certs| matches!(certs, Some(certs) if certs.first() == Some(cert)))
+    }
+}
+
+async fn handle(req: Request<Body>) -> Result<Response<Body>, Infallible> {
+    let method = req.method().clone();
+    let segments: Vec<String> = req
+        .uri()
+        .path()
+        .split('/')
+        .filter(|s| !s.is_empty())
+        .map(String::from)
+        .collect();
+
+    let _ = hyper::body::to_bytes(req.into_body()).await;
+
+    let body = match (method, segments.len()) {
+        (Method::POST, 1) => serde_json::to_vec(&InitializeResult::Ok {
+            id: Uuid::nil(),
+            duplicated: false,
+            version: 0,
+            capabilities: Vec::new(),
+        }),
+        (Method::PUT, 3) => serde_json::to_vec(&UploadChunkResult::Ok),
+        (Method::POST, 2) => serde_json::to_vec(&CompleteResult::Ok),
+        _ => return Ok(Response::builder().status(404).body(Body::empty()).unwrap()),
+    };
+
+    Ok(Response::new(Body::from(body.unwrap())))
+}
+
+fn test_file(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("chua-tls-{}-{}.txt", std::process::id(), name));
+    std::fs::write(&path, b"hello, chua").unwrap();
+    path
+}
+
+fn client_certificate() -> rustls::Certificate {
+    certs(&mut Cursor::new(CLIENT_IDENTITY)).unwrap().remove(0)
+}
+
+#[tokio::test]
+async fn rejects_servers_signed_by_unknown_cas() {
+    let server = Server::start().await;
+
+    let result = upload_with_options(
+        &server.base_url(),
+        test_file("unknown-ca"),
+        4,
+        1,
+        &ClientOptions::default(),
+    )
+    .await;
+
+    // 握手时校验证书链失败
+    let error = format!("{:?}", result.unwrap_err());
+    assert!(error.contains("UnknownIssuer"), "{}", error);
+    assert_eq!(server.connections(), 0);
+}
+
+#[tokio::test]
+async fn trusts_extra_root_certificates() {
+    let server = Server::start().await;
+
+    let options = ClientOptions {
+        root_certificates: vec![CA.to_vec()],
+        ..ClientOptions::default()
+    };
+
+    let id = upload_with_options(&server.base_url(), test_file("root"), 4, 1, &options)
+        .await
+        .unwrap();
+
+    assert_eq!(id, Uuid::nil());
+    assert!(server.connections() > 0);
+    assert!(server
+        .peer_certificates
+        .lock()
+        .unwrap()
+        .iter()
+        .all(Option::is_none));
+}
+
+#[tokio::test]
+async fn presents_the_client_identity() {
+    let server = Server::start().await;
+
+    let options = ClientOptions {
+        root_certificates: vec![CA.to_vec()],
+        identity: Some(CLIENT_IDENTITY.to_vec()),
+        ..ClientOptions::default()
+    };
+
+    upload_with_options(&server.base_url(), test_file("identity"), 4, 1, &options)
+        .await
+        .unwrap();
+
+    assert!(server.connections() > 0);
+    assert!(server.all_presented(&client_certificate()));
+}
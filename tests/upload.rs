@@ -0,0 +1,210 @@
+//! 在本地启动一个按协议应答的 HTTP 服务，检查上传过程中的事件和发出的请求
+#![cfg(not(target_arch = "wasm32"))]
+
+use chua::{
+    upload_cancelable, upload_with_events, AbortResult, CancelToken, ChuaError, ClientOptions,
+    CompleteResult, FileInfo, InitializeResult, UploadChunkResult, UploadEvent, CAPABILITIES,
+    PROTOCOL_VERSION,
+};
+use futures::StreamExt;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response};
+use std::convert::Infallible;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use uuid::Uuid;
+
+/// 本地的 HTTP 服务，记录收到的每个请求的方法和路径
+struct Server {
+    port: u16,
+    requests: Arc<Mutex<Vec<(Method, String)>>>,
+}
+
+impl Server {
+    /// `stall_chunks` 为 `true` 时分片请求一直不返回，用来模拟上传中的分片
+    fn start(stall_chunks: bool) -> Self {
+        let requests = Arc::new(Mutex::new(Vec::new()));
+
+        let service = {
+            let requests = requests.clone();
+
+            make_service_fn(move |_| {
+                let requests = requests.clone();
+
+                async move {
+                    Ok::<_, Infallible>(service_fn(move |req| {
+                        handle(req, requests.clone(), stall_chunks)
+                    }))
+                }
+            })
+        };
+
+        let server = hyper::Server::bind(&([127, 0, 0, 1], 0).into()).serve(service);
+        let port = server.local_addr().port();
+
+        tokio::spawn(server);
+
+        Self { port, requests }
+    }
+
+    fn base_url(&self) -> String {
+        format!("http://127.0.0.1:{}/", self.port)
+    }
+
+    fn requests(&self) -> Vec<(Method, String)> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+async fn handle(
+    req: Request<Body>,
+    requests: Arc<Mutex<Vec<(Method, String)>>>,
+    stall_chunks: bool,
+) -> Result<Response<Body>, Infallible> {
+    let method = req.method().clone();
+    let path = req.uri().path().to_string();
+    let segments = path.split('/').filter(|s| !s.is_empty()).count();
+
+    requests.lock().unwrap().push((method.clone(), path));
+
+    let _ = hyper::body::to_bytes(req.into_body()).await;
+
+    let body = match (method, segments) {
+        (Method::POST, 1) => serde_json::to_vec(&InitializeResult::Ok {
+            id: Uuid::nil(),
+            duplicated: false,
+            version: PROTOCOL_VERSION,
+            capabilities: CAPABILITIES.to_vec(),
+        }),
+        (Method::PUT, 3) if stall_chunks => futures::future::pending().await,
+        (Method::PUT, 3) => serde_json::to_vec(&UploadChunkResult::Ok),
+        (Method::POST, 2) => serde_json::to_vec(&CompleteResult::Ok),
+        (Method::DELETE, 2) => serde_json::to_vec(&AbortResult::Ok),
+        _ => return Ok(Response::builder().status(404).body(Body::empty()).unwrap()),
+    };
+
+    Ok(Response::new(Body::from(body.unwrap())))
+}
+
+fn test_file(name: &str) -> PathBuf {
+    let path =
+        std::env::temp_dir().join(format!("chua-upload-{}-{}.txt", std::process::id(), name));
+    std::fs::write(&path, b"hello, chua").unwrap();
+    path
+}
+
+/// 去掉事件中的具体数值，只比较顺序
+fn kind(event: &UploadEvent) -> String {
+    match event {
+        UploadEvent::Initialized { .. } => "Initialized".into(),
+        UploadEvent::Resumed { .. } => "Resumed".into(),
+        UploadEvent::ChunkStarted { index, .. } => format!("ChunkStarted({})", index),
+        UploadEvent::ChunkAcknowledged { index, .. } => format!("ChunkAcknowledged({})", index),
+        UploadEvent::ChunkRetried { index, .. } => format!("ChunkRetried({})", index),
+        UploadEvent::ChunkFailed { index, .. } => format!("ChunkFailed({})", index),
+        UploadEvent::Completing => "Completing".into(),
+        UploadEvent::Completed { .. } => "Completed".into(),
+    }
+}
+
+#[tokio::test]
+async fn emits_events_in_order() {
+    let server = Server::start(false);
+    let base_url = server.base_url();
+
+    // 只有一个并行任务，分片按顺序上传
+    let (future, events) = upload_with_events(
+        &base_url,
+        test_file("events"),
+        4,
+        1,
+        ClientOptions::default(),
+    );
+
+    let (id, events) = futures::join!(future, events.collect::<Vec<_>>());
+
+    assert_eq!(id.unwrap(), Uuid::nil());
+    assert_eq!(
+        events.iter().map(kind).collect::<Vec<_>>(),
+        vec![
+            "Initialized",
+            "ChunkStarted(0)",
+            "ChunkAcknowledged(0)",
+            "ChunkStarted(1)",
+            "ChunkAcknowledged(1)",
+            "ChunkStarted(2)",
+            "ChunkAcknowledged(2)",
+            "Completing",
+            "Completed",
+        ]
+    );
+
+    match &events[0] {
+        UploadEvent::Initialized {
+            size, chunk_size, ..
+        } => assert_eq!((*size, *chunk_size), (11, 4)),
+        event => panic!("unexpected {:?}", event),
+    }
+
+    // 最后一片不足分片大小
+    assert!(matches!(
+        events[5],
+        UploadEvent::ChunkStarted { index: 2, size: 3 }
+    ));
+    assert!(matches!(
+        events.last(),
+        Some(UploadEvent::Completed { id }) if *id == Uuid::nil()
+    ));
+
+    // 每个分片的确认都对应一个请求
+    let requests = server.requests();
+    assert_eq!(
+        requests.iter().filter(|(m, _)| *m == Method::PUT).count(),
+        3
+    );
+    assert_eq!(
+        requests.last(),
+        Some(&(Method::POST, format!("/file/{}", Uuid::nil())))
+    );
+}
+
+#[tokio::test]
+async fn canceling_aborts_the_upload_on_the_server() {
+    let server = Server::start(true);
+    let base_url = server.base_url();
+    let cancel = CancelToken::new();
+
+    let (future, mut events) = upload_cancelable(
+        &base_url,
+        test_file("cancel"),
+        4,
+        2,
+        FileInfo::default(),
+        ClientOptions::default(),
+        cancel.clone(),
+    );
+
+    // 等分片请求到达服务端后再取消，此时分片还没有得到响应
+    let cancel_in_flight = async {
+        while let Some(event) = events.next().await {
+            if let UploadEvent::ChunkStarted { .. } = event {
+                while !server.requests().iter().any(|(m, _)| *m == Method::PUT) {
+                    tokio::time::delay_for(Duration::from_millis(10)).await;
+                }
+
+                cancel.cancel();
+            }
+        }
+    };
+
+    let (result, _) = futures::join!(future, cancel_in_flight);
+
+    assert!(matches!(result, Err(ChuaError::Aborted)), "{:?}", result);
+
+    let upload = format!("/file/{}", Uuid::nil());
+    let requests = server.requests();
+
+    assert!(requests.contains(&(Method::DELETE, upload.clone())));
+    assert!(!requests.contains(&(Method::POST, upload)));
+}